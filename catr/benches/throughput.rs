@@ -0,0 +1,71 @@
+//! Compares the plain copy path with the line-by-line path on a large file.
+//!
+//! Run with `cargo bench`. `-s` forces the line loop without changing the
+//! output because the generated input has no repeated blank lines.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const FILE_SIZE: usize = 256 * 1024 * 1024;
+const RUNS: u32 = 3;
+
+fn make_input() -> PathBuf {
+    let path = std::env::temp_dir().join("catr-bench-input.txt");
+    let mut file = BufWriter::new(File::create(&path).unwrap());
+    let line = b"The quick brown fox jumps over the lazy dog.\n";
+    for _ in 0..FILE_SIZE / line.len() {
+        file.write_all(line).unwrap();
+    }
+    file.flush().unwrap();
+    path
+}
+
+fn time(args: &[&str], output: &Path) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let output = File::create(output).unwrap();
+        let start = Instant::now();
+        let status = Command::new(env!("CARGO_BIN_EXE_catr"))
+            .args(args)
+            .stdout(Stdio::from(output))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let mib = FILE_SIZE as f64 / (1024.0 * 1024.0);
+    println!(
+        "{name:<12} {:>8.1} ms {:>10.1} MiB/s",
+        elapsed.as_secs_f64() * 1000.0,
+        mib / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let input = make_input();
+    let input = input.to_str().unwrap();
+    let output_path = std::env::temp_dir().join("catr-bench-output.txt");
+
+    for output in [Path::new("/dev/null"), &output_path] {
+        println!("output to {}:", output.display());
+        let plain = time(&[input], output);
+        let lines = time(&["-s", input], output);
+        report("plain copy", plain);
+        report("line loop", lines);
+        println!(
+            "{:<12} {:>8.1}x",
+            "speedup",
+            lines.as_secs_f64() / plain.as_secs_f64()
+        );
+    }
+
+    fs::remove_file(input).ok();
+    fs::remove_file(output_path).ok();
+}
@@ -1,17 +1,399 @@
-use anyhow::Result;
-use clap::{Arg, ArgAction, Command};
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use anyhow::{anyhow, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bzip2::bufread::MultiBzDecoder;
+use clap::{error::ErrorKind, Arg, ArgAction, Command, ValueEnum};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use flate2::bufread::{GzDecoder, MultiGzDecoder};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::collections::VecDeque;
+use std::io::{
+    self, BufRead, BufReader, BufWriter, IsTerminal, Read, StdoutLock, Write,
+};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use xz2::bufread::XzDecoder;
+
+/// Read buffer size for the plain copy path
+const COPY_BUFFER_SIZE: usize = 256 * 1024;
+
+// grep's default colors for file names, line numbers and separators
+const COLOR_FILENAME: &str = "\x1b[35m";
+const COLOR_LINE_NUMBER: &str = "\x1b[32m";
+const COLOR_SEPARATOR: &str = "\x1b[36m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+const BOM: &[u8] = "\u{feff}".as_bytes();
+
+/// Bytes per row of --hex output
+const HEX_ROW_SIZE: usize = 16;
+
+/// How often --follow checks for new data when no change event arrives
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 struct Args {
     files: Vec<String>,
     number_lines: bool,
     number_nonblank_lines: bool,
+    show_ends: bool,
+    show_tabs: bool,
+    show_nonprinting: bool,
+    squeeze_blank: bool,
+    number_per_file: bool,
+    number_width: usize,
+    number_separator: String,
+    number_align: NumberAlign,
+    number_start: i64,
+    number_increment: i64,
+    decompress: bool,
+    no_decompress: bool,
+    line_ranges: Vec<LineRange>,
+    with_filename: bool,
+    color: bool,
+    from_encoding: Option<&'static Encoding>,
+    to_encoding: Option<&'static Encoding>,
+    strip_bom: bool,
+    null_data: bool,
+    expand_tabs: Option<usize>,
+    strip_ansi: bool,
+    json: bool,
+    hex: bool,
+    offset: u64,
+    length: Option<u64>,
+    unbuffered: bool,
+    follow: bool,
+    recursive: bool,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    gitignore: bool,
+    header: Option<String>,
+    separator: Option<String>,
+    output: Option<String>,
+    tee: bool,
+}
+
+/// A line of --json output. Lines that are not valid UTF-8 carry their
+/// bytes in `text_base64` instead of `text`.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    file: &'a str,
+    line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_base64: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum NumberAlign {
+    /// Left-justified, padded with spaces
+    Left,
+    /// Right-justified, padded with spaces
+    Right,
+    /// Right-justified, padded with zeros
+    Zero,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+/// An inclusive range of 1-based line numbers. Negative bounds count from
+/// the last line (-1), and a missing bound leaves that side open.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct LineRange {
+    start: Option<i64>,
+    end: Option<i64>,
+}
+
+impl LineRange {
+    /// Tests a line given its position from the start and, once the end of
+    /// input is known, its negative position from the end. `None` means the
+    /// line is further from the end than any negative bound reaches.
+    fn contains(&self, line: i64, from_end: Option<i64>) -> bool {
+        let after_start = match self.start {
+            None => true,
+            Some(start) if start > 0 => line >= start,
+            Some(start) => from_end.is_some_and(|pos| pos >= start),
+        };
+        let before_end = match self.end {
+            None => true,
+            Some(end) if end > 0 => line <= end,
+            Some(end) => from_end.is_none_or(|pos| pos <= end),
+        };
+        after_start && before_end
+    }
+
+    /// How many lines from the end must be known to evaluate the range
+    fn lookahead(&self) -> usize {
+        [self.start, self.end]
+            .into_iter()
+            .flatten()
+            .filter(|bound| *bound < 0)
+            .map(|bound| bound.unsigned_abs() as usize)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Looks up an encoding by any of its WHATWG labels, e.g. `utf-16le`,
+/// `latin1`, `windows-1252` or `shift_jis`.
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("unknown encoding '{label}'"))
+}
+
+fn parse_glob(pattern: &str) -> Result<Glob, String> {
+    Glob::new(pattern).map_err(|err| err.to_string())
+}
+
+/// Parses `START:END`, `START:`, `:END` or a single line number.
+fn parse_line_range(value: &str) -> Result<LineRange, String> {
+    let parse_bound = |bound: &str| -> Result<Option<i64>, String> {
+        match bound {
+            "" => Ok(None),
+            _ => match bound.parse() {
+                Ok(0) => Err("line numbers start at 1".to_string()),
+                Ok(num) => Ok(Some(num)),
+                Err(_) => Err(format!("invalid line number '{bound}'")),
+            },
+        }
+    };
+    match value.split_once(':') {
+        Some((start, end)) => Ok(LineRange {
+            start: parse_bound(start)?,
+            end: parse_bound(end)?,
+        }),
+        None => match parse_bound(value)? {
+            Some(line) => Ok(LineRange {
+                start: Some(line),
+                end: Some(line),
+            }),
+            None => Err("empty line range".to_string()),
+        },
+    }
+}
+
+/// Filters rendered lines by `--lines`. Lines are held back only as long
+/// as needed to resolve ranges that count from the end.
+struct LineSelector {
+    ranges: Vec<LineRange>,
+    lookahead: usize,
+    line: i64,
+    /// Held-back output; entries without a line number are headers, which
+    /// are always written
+    pending: VecDeque<(Option<i64>, Vec<u8>)>,
+    pending_lines: usize,
+    last_selected: bool,
+}
+
+impl LineSelector {
+    fn new(ranges: &[LineRange]) -> Self {
+        Self {
+            ranges: ranges.to_vec(),
+            lookahead: ranges
+                .iter()
+                .map(LineRange::lookahead)
+                .max()
+                .unwrap_or(0),
+            line: 0,
+            pending: VecDeque::new(),
+            pending_lines: 0,
+            last_selected: false,
+        }
+    }
+
+    fn selects(&self, line: i64, from_end: Option<i64>) -> bool {
+        self.ranges.iter().any(|range| range.contains(line, from_end))
+    }
+
+    /// Adds the output for a line, or for the rest of an unterminated line
+    /// when `line_start` is false.
+    fn push(
+        &mut self,
+        out: &mut impl Write,
+        line_start: bool,
+        rendered: Vec<u8>,
+    ) -> io::Result<()> {
+        if !line_start {
+            match self.pending.back_mut() {
+                Some((_, text)) => text.extend_from_slice(&rendered),
+                None if self.last_selected => out.write_all(&rendered)?,
+                None => {}
+            }
+            return Ok(());
+        }
+
+        self.line += 1;
+        self.pending.push_back((Some(self.line), rendered));
+        self.pending_lines += 1;
+        while self.pending_lines > self.lookahead {
+            match self.pending.pop_front() {
+                Some((Some(line), text)) => {
+                    self.pending_lines -= 1;
+                    self.last_selected = self.selects(line, None);
+                    if self.last_selected {
+                        out.write_all(&text)?;
+                    }
+                }
+                Some((None, text)) => out.write_all(&text)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds output that is not a line, keeping it in order with the lines
+    /// around it.
+    fn push_header(
+        &mut self,
+        out: &mut impl Write,
+        header: Vec<u8>,
+    ) -> io::Result<()> {
+        if self.pending.is_empty() {
+            out.write_all(&header)
+        } else {
+            self.pending.push_back((None, header));
+            Ok(())
+        }
+    }
+
+    /// Resolves the held-back lines at the end of a file or of the stream.
+    fn finish(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let total = self.line;
+        while let Some((line, text)) = self.pending.pop_front() {
+            let selected = match line {
+                Some(line) => self.selects(line, Some(line - total - 1)),
+                None => true,
+            };
+            if selected {
+                out.write_all(&text)?;
+            }
+        }
+        self.line = 0;
+        self.pending_lines = 0;
+        self.last_selected = false;
+        Ok(())
+    }
+}
+
+/// Waits for a followed file to grow. The platform's file watcher
+/// (inotify on Linux) wakes it up on changes; when no watcher is available
+/// it falls back to polling.
+struct Follower {
+    _watcher: Option<RecommendedWatcher>,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+impl Follower {
+    fn new(filename: &str) -> Self {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender)
+            .and_then(|mut watcher| {
+                let path = Path::new(filename);
+                watcher.watch(path, RecursiveMode::NonRecursive)?;
+                Ok(watcher)
+            })
+            .ok();
+        Self {
+            _watcher: watcher,
+            events,
+        }
+    }
+
+    /// Returns after a change event or, at the latest, one poll interval.
+    fn wait(&self) {
+        match self.events.recv_timeout(FOLLOW_POLL_INTERVAL) {
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(FOLLOW_POLL_INTERVAL)
+            }
+            _ => while self.events.try_recv().is_ok() {},
+        }
+    }
+}
+
+/// Writes to the -o file and stdout at once for --tee.
+struct Tee<'a> {
+    file: &'a File,
+    stdout: StdoutLock<'static>,
+}
+
+impl Write for Tee<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write_all(buf)?;
+        self.stdout.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Recognizes a compressed stream by its header. Beyond the magic
+    /// bytes, the fields right after them must be valid too, so plain text
+    /// that happens to start like "BZh" is left alone.
+    fn detect(header: &[u8]) -> Option<Self> {
+        const BZIP2_BLOCK: [u8; 6] = [0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+        const BZIP2_END: [u8; 6] = [0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+        match header {
+            // Deflate with no reserved flags, and a header the decoder
+            // accepts
+            [0x1f, 0x8b, 0x08, flags, ..]
+                if flags & 0xe0 == 0
+                    && GzDecoder::new(header).header().is_some() =>
+            {
+                Some(Self::Gzip)
+            }
+            // A block size, then the magic of a block or of the stream end
+            [b'B', b'Z', b'h', b'1'..=b'9', block @ ..]
+                if block.starts_with(&BZIP2_BLOCK)
+                    || block.starts_with(&BZIP2_END) =>
+            {
+                Some(Self::Bzip2)
+            }
+            // Stream flags with only a check type set
+            [0xfd, b'7', b'z', b'X', b'Z', 0, 0, check, ..]
+                if check & 0xf0 == 0 =>
+            {
+                Some(Self::Xz)
+            }
+            // A frame header descriptor with its reserved bit clear
+            [0x28, 0xb5, 0x2f, 0xfd, descriptor, ..]
+                if descriptor & 0x08 == 0 =>
+            {
+                Some(Self::Zstd)
+            }
+            _ => None,
+        }
+    }
 }
 
 fn get_args() -> Args {
-    let matches = Command::new("catr")
+    let mut command = Command::new("catr")
     .version("0.1.0")
     .about("Rust version of cat")
     .arg(
@@ -38,55 +420,1429 @@ fn get_args() -> Args {
             .action(ArgAction::SetTrue)            
 
     )
-    .get_matches();
+    .arg(
+        Arg::new("number_per_file")
+            .long("number-per-file")
+            .help("Restart line numbers and --lines ranges for each file")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("number_width")
+            .long("number-width")
+            .value_name("WIDTH")
+            .help("Width of line numbers")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("6"),
+    )
+    .arg(
+        Arg::new("number_separator")
+            .long("number-separator")
+            .value_name("STRING")
+            .help("Text between a line number and the line")
+            .default_value("\t")
+            .hide_default_value(true),
+    )
+    .arg(
+        Arg::new("number_align")
+            .long("number-align")
+            .value_name("ALIGN")
+            .help("Alignment of line numbers")
+            .value_parser(clap::value_parser!(NumberAlign))
+            .default_value("right"),
+    )
+    .arg(
+        Arg::new("number_start")
+            .long("number-start")
+            .value_name("NUMBER")
+            .help("First line number")
+            .value_parser(clap::value_parser!(i64))
+            .default_value("1")
+            .allow_negative_numbers(true),
+    )
+    .arg(
+        Arg::new("number_increment")
+            .long("number-increment")
+            .value_name("NUMBER")
+            .help("Line number increment")
+            .value_parser(clap::value_parser!(i64))
+            .default_value("1")
+            .allow_negative_numbers(true),
+    )
+    .arg(
+        Arg::new("decompress")
+            .long("decompress")
+            .help("Also decompress standard input (files are detected \
+                   automatically)")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("no_decompress")
+            .long("no-decompress")
+            .help("Copy compressed files as they are")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("decompress"),
+    )
+    .arg(
+        Arg::new("lines")
+            .long("lines")
+            .value_name("RANGES")
+            .help("Only print lines in START:END ranges (negative values \
+                   count from the end)")
+            .value_parser(parse_line_range)
+            .value_delimiter(',')
+            .action(ArgAction::Append)
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::new("with_filename")
+            .long("with-filename")
+            .short('H')
+            .help("Prefix each line with NAME: (NAME:LINE: with -n or -b, \
+                   numbering the lines of each file)")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("color")
+            .long("color")
+            .value_name("WHEN")
+            .help("Colorize --with-filename prefixes")
+            .value_parser(clap::value_parser!(ColorWhen))
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_value("auto")
+            .default_missing_value("always"),
+    )
+    .arg(
+        Arg::new("from_encoding")
+            .long("from-encoding")
+            .value_name("ENCODING")
+            .help("Encoding of inputs without a BOM [default: UTF-8]")
+            .value_parser(parse_encoding),
+    )
+    .arg(
+        Arg::new("to_encoding")
+            .long("to-encoding")
+            .value_name("ENCODING")
+            .help("Encoding of the output [default: UTF-8]")
+            .value_parser(parse_encoding),
+    )
+    .arg(
+        Arg::new("strip_bom")
+            .long("strip-bom")
+            .help("Remove byte order marks from the output")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("null_data")
+            .long("null-data")
+            .short('z')
+            .help("Records are terminated by NUL instead of newline")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("expand_tabs")
+            .long("expand-tabs")
+            .value_name("N")
+            .help("Convert tabs to spaces with tab stops every N columns")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("8"),
+    )
+    .arg(
+        Arg::new("strip_ansi")
+            .long("strip-ansi")
+            .help("Remove ANSI color and terminal escape sequences")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("json")
+            .long("json")
+            .help("Print each line as a JSON object with its file and \
+                   line number")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all([
+                "number",
+                "number_nonblank",
+                "with_filename",
+                "show_all",
+                "show_nonprinting_ends",
+                "show_ends",
+                "show_nonprinting_tabs",
+                "show_tabs",
+                "show_nonprinting",
+                "to_encoding",
+                "header",
+                "separator",
+            ]),
+    )
+    .arg(
+        Arg::new("hex")
+            .long("hex")
+            .short('x')
+            .help("Print a canonical hex+ASCII dump")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all([
+                "number",
+                "number_nonblank",
+                "with_filename",
+                "show_all",
+                "show_nonprinting_ends",
+                "show_ends",
+                "show_nonprinting_tabs",
+                "show_tabs",
+                "show_nonprinting",
+                "squeeze_blank",
+                "lines",
+                "null_data",
+                "expand_tabs",
+                "strip_ansi",
+                "json",
+                "follow",
+                "header",
+                "separator",
+                "from_encoding",
+                "to_encoding",
+                "strip_bom",
+            ]),
+    )
+    .arg(
+        Arg::new("offset")
+            .long("offset")
+            .value_name("BYTES")
+            .help("Skip BYTES bytes of input before the hex dump")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("0")
+            .requires("hex"),
+    )
+    .arg(
+        Arg::new("length")
+            .long("length")
+            .value_name("BYTES")
+            .help("Dump at most BYTES bytes of input")
+            .value_parser(clap::value_parser!(u64))
+            .requires("hex"),
+    )
+    .arg(
+        Arg::new("unbuffered")
+            .short('u')
+            .long("unbuffered")
+            .help("Write output as soon as it is read")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("follow")
+            .short('f')
+            .long("follow")
+            .help("Keep printing data appended to the last file")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("recursive")
+            .short('r')
+            .long("recursive")
+            .help("Read all files under each directory, sorted by name")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("include")
+            .long("include")
+            .value_name("GLOB")
+            .help("With -r, only read files whose name matches GLOB")
+            .value_parser(parse_glob)
+            .action(ArgAction::Append)
+            .requires("recursive"),
+    )
+    .arg(
+        Arg::new("exclude")
+            .long("exclude")
+            .value_name("GLOB")
+            .help("With -r, skip files whose name matches GLOB")
+            .value_parser(parse_glob)
+            .action(ArgAction::Append)
+            .requires("recursive"),
+    )
+    .arg(
+        Arg::new("gitignore")
+            .long("gitignore")
+            .help("With -r, skip files ignored by .gitignore and .ignore \
+                   files, and .git directories")
+            .action(ArgAction::SetTrue)
+            .requires("recursive"),
+    )
+    .arg(
+        Arg::new("header")
+            .long("header")
+            .value_name("FORMAT")
+            .help("Print a header before each file; FORMAT may use {name}, \
+                   {size} and {index} [default: ==> {name} <==]")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("==> {name} <=="),
+    )
+    .arg(
+        Arg::new("separator")
+            .long("separator")
+            .value_name("STRING")
+            .help("Print a STRING line between files [default: a blank \
+                   line with --header]")
+            .allow_hyphen_values(true),
+    )
+    .arg(
+        Arg::new("output")
+            .short('o')
+            .long("output")
+            .value_name("FILE")
+            .help("Write to FILE, replacing it only once all input is read")
+            .conflicts_with("follow"),
+    )
+    .arg(
+        Arg::new("tee")
+            .long("tee")
+            .help("With -o, also write to stdout")
+            .action(ArgAction::SetTrue)
+            .requires("output"),
+    )
+    .arg(
+        Arg::new("squeeze_blank")
+            .long("squeeze-blank")
+            .short('s')
+            .help("Suppress repeated empty output lines")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("show_all")
+            .long("show-all")
+            .short('A')
+            .help("Equivalent to -vET")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("show_nonprinting_ends")
+            .short('e')
+            .help("Equivalent to -vE")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("show_ends")
+            .long("show-ends")
+            .short('E')
+            .help("Display $ at end of each line")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("show_nonprinting_tabs")
+            .short('t')
+            .help("Equivalent to -vT")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("show_tabs")
+            .long("show-tabs")
+            .short('T')
+            .help("Display TAB characters as ^I")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(
+        Arg::new("show_nonprinting")
+            .long("show-nonprinting")
+            .short('v')
+            .help("Use ^ and M- notation, except for LFD and TAB")
+            .action(ArgAction::SetTrue),
+    );
+    let matches = command.get_matches_mut();
+
+    let line_ranges: Vec<LineRange> = matches
+        .get_many("lines")
+        .map(|ranges| ranges.cloned().collect())
+        .unwrap_or_default();
+    // A followed file has no last line to count back from
+    if matches.get_flag("follow")
+        && line_ranges.iter().any(|range| range.lookahead() > 0)
+    {
+        command
+            .error(
+                ErrorKind::ArgumentConflict,
+                "negative --lines bounds cannot be used with '--follow'",
+            )
+            .exit();
+    }
+
+    let show_all = matches.get_flag("show_all");
+    let show_nonprinting_ends = matches.get_flag("show_nonprinting_ends");
+    let show_nonprinting_tabs = matches.get_flag("show_nonprinting_tabs");
 
     Args { 
         files: matches.get_many("files").unwrap().cloned().collect(), 
         number_lines: matches.get_flag("number"), 
-        number_nonblank_lines: matches.get_flag("number_nonblank")
+        number_nonblank_lines: matches.get_flag("number_nonblank"),
+        show_ends: show_all
+            || show_nonprinting_ends
+            || matches.get_flag("show_ends"),
+        show_tabs: show_all
+            || show_nonprinting_tabs
+            || matches.get_flag("show_tabs"),
+        show_nonprinting: show_all
+            || show_nonprinting_ends
+            || show_nonprinting_tabs
+            || matches.get_flag("show_nonprinting"),
+        squeeze_blank: matches.get_flag("squeeze_blank"),
+        number_per_file: matches.get_flag("number_per_file"),
+        number_width: matches.get_one("number_width").cloned().unwrap(),
+        number_separator: matches
+            .get_one("number_separator")
+            .cloned()
+            .unwrap(),
+        number_align: matches.get_one("number_align").cloned().unwrap(),
+        number_start: matches.get_one("number_start").cloned().unwrap(),
+        number_increment: matches
+            .get_one("number_increment")
+            .cloned()
+            .unwrap(),
+        decompress: matches.get_flag("decompress"),
+        no_decompress: matches.get_flag("no_decompress"),
+        line_ranges,
+        with_filename: matches.get_flag("with_filename"),
+        color: match matches.get_one("color").cloned().unwrap() {
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+            ColorWhen::Auto => io::stdout().is_terminal(),
+        },
+        from_encoding: matches.get_one("from_encoding").cloned(),
+        to_encoding: matches.get_one("to_encoding").cloned(),
+        strip_bom: matches.get_flag("strip_bom"),
+        null_data: matches.get_flag("null_data"),
+        expand_tabs: matches
+            .get_one::<u64>("expand_tabs")
+            .map(|&tab_size| tab_size as usize),
+        strip_ansi: matches.get_flag("strip_ansi"),
+        json: matches.get_flag("json"),
+        hex: matches.get_flag("hex"),
+        offset: matches.get_one("offset").cloned().unwrap(),
+        length: matches.get_one("length").cloned(),
+        unbuffered: matches.get_flag("unbuffered"),
+        follow: matches.get_flag("follow"),
+        recursive: matches.get_flag("recursive"),
+        include: matches
+            .get_many("include")
+            .map(|globs| globs.cloned().collect())
+            .unwrap_or_default(),
+        exclude: matches
+            .get_many("exclude")
+            .map(|globs| globs.cloned().collect())
+            .unwrap_or_default(),
+        gitignore: matches.get_flag("gitignore"),
+        header: matches.get_one("header").cloned(),
+        separator: matches.get_one("separator").cloned(),
+        output: matches.get_one("output").cloned(),
+        tee: matches.get_flag("tee"),
     }
 }
 
-fn open(filename: &str) -> Result<Box<dyn BufRead>> {
+/// Opens an input, decoding named files by default and stdin only with
+/// --decompress. Hex dumps show the bytes on disk unless --decompress is
+/// given.
+fn open(filename: &str, args: &Args) -> Result<Box<dyn BufRead>> {
     match filename {
+        "-" if args.decompress => decompress(BufReader::new(io::stdin())),
         "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
-    }
-}
-
-fn run(args: Args) -> Result<()> {
-    for filename in args.files {
-        match open(&filename) {
-            Err(err) => eprintln!("Failed to open {filename}: {err}"),
-            Ok(file) => {
-                let mut prev_num = 0;
-                for (line_num, line_result) in file.lines().enumerate() {
-                    let line_read = line_result?;
-
-                    
-                    let line = if args.number_lines {
-                            format!("{:>6}\t{line_read}", line_num + 1)
-                        } else if args.number_nonblank_lines {
-                            if line_read.is_empty() {
-                                format!("")
-                            } else {
-                                prev_num += 1;
-                                format!("{prev_num:>6}\t{line_read}")
+        _ if args.no_decompress || (args.hex && !args.decompress) => {
+            Ok(Box::new(BufReader::new(File::open(filename)?)))
+        }
+        _ => decompress(BufReader::new(File::open(filename)?)),
+    }
+}
+
+/// Wraps `reader` in a decoder if it starts with a known compression
+/// format. Concatenated streams are decoded in full, like `zcat`.
+fn decompress<R: BufRead + 'static>(mut reader: R) -> Result<Box<dyn BufRead>> {
+    Ok(match Compression::detect(reader.fill_buf()?) {
+        None => Box::new(reader),
+        Some(Compression::Gzip) => {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        }
+        Some(Compression::Bzip2) => {
+            Box::new(BufReader::new(MultiBzDecoder::new(reader)))
+        }
+        Some(Compression::Xz) => {
+            Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader)))
+        }
+        Some(Compression::Zstd) => {
+            Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+        }
+    })
+}
+
+/// Returns true when inputs are decoded to UTF-8 and the output re-encoded.
+fn is_transcoding(args: &Args) -> bool {
+    args.from_encoding.is_some()
+        || args.to_encoding.is_some()
+        || args.strip_bom
+}
+
+/// Decodes an input to UTF-8. A UTF-8 or UTF-16 BOM selects the encoding,
+/// otherwise --from-encoding does. UTF-8 passes through unchanged, invalid
+/// sequences included. The BOM is removed; the returned flag tells `run`
+/// whether there was one.
+fn decode(
+    mut reader: Box<dyn BufRead>,
+    args: &Args,
+) -> Result<(Box<dyn BufRead>, bool)> {
+    let has_bom = Encoding::for_bom(reader.fill_buf()?).is_some();
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(Some(args.from_encoding.unwrap_or(UTF_8)))
+        .bom_override(true)
+        .utf8_passthru(true)
+        .strip_bom(true)
+        .build(reader);
+    Ok((Box::new(BufReader::new(decoder)), has_bom))
+}
+
+/// Converts rendered UTF-8 output to --to-encoding. encoding_rs has no
+/// UTF-16 encoders, so those are produced here. Characters the encoding
+/// cannot represent are an error rather than the HTML numeric references
+/// encoding_rs would put in their place.
+fn encode(rendered: &[u8], encoding: &'static Encoding) -> io::Result<Vec<u8>> {
+    let text = String::from_utf8_lossy(rendered);
+    if encoding == UTF_16LE {
+        return Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    } else if encoding == UTF_16BE {
+        return Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+    }
+
+    let (encoded, _, had_errors) = encoding.encode(&text);
+    if !had_errors {
+        return Ok(encoded.into_owned());
+    }
+    let unmappable = text
+        .chars()
+        .find(|c| encoding.encode(c.encode_utf8(&mut [0; 4])).2)
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "cannot encode '{unmappable}' (U+{:04X}) as {}",
+            unmappable as u32,
+            encoding.name()
+        ),
+    ))
+}
+
+/// Returns true when the output is an unmodified copy of the input.
+fn is_plain_copy(args: &Args) -> bool {
+    !(args.number_lines
+        || args.number_nonblank_lines
+        || args.show_ends
+        || args.show_tabs
+        || args.show_nonprinting
+        || args.squeeze_blank
+        || !args.line_ranges.is_empty()
+        || args.with_filename
+        || is_transcoding(args)
+        || args.expand_tabs.is_some()
+        || args.strip_ansi
+        || args.json
+        || args.follow
+        || args.header.is_some()
+        || args.separator.is_some())
+}
+
+/// Copies `reader` to `out`, flushing every chunk so data from a pipe shows
+/// up as it arrives. Errors reading the input come back in the inner
+/// result, so the caller can report them against the file and go on with
+/// the next one; write errors are fatal.
+fn copy<W: Write>(
+    reader: &mut impl Read,
+    out: &mut BufWriter<W>,
+) -> io::Result<io::Result<()>> {
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    loop {
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(Ok(())),
+            Ok(bytes_read) => bytes_read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Ok(Err(err)),
+        };
+        out.write_all(&buffer[..bytes_read])?;
+        out.flush()?;
+    }
+}
+
+/// Errors only writing can cause. `io::copy` may move the data without it
+/// passing through catr, so these tell a full or closed output, which is
+/// fatal, from a bad input, which is reported and skipped.
+fn is_write_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::WriteZero
+            | io::ErrorKind::StorageFull
+            | io::ErrorKind::QuotaExceeded
+            | io::ErrorKind::FileTooLarge
+    )
+}
+
+/// Copies a regular file with `io::copy`, which hands the work to
+/// `copy_file_range`, `splice` or `sendfile` on Linux when the file
+/// descriptors allow it. Read errors come back in the inner result as with
+/// `copy`.
+fn copy_file<W: Write>(
+    reader: &mut BufReader<File>,
+    out: &mut BufWriter<W>,
+) -> io::Result<io::Result<()>> {
+    match io::copy(reader, out) {
+        Ok(_) => Ok(Ok(())),
+        Err(err) if is_write_error(&err) => Err(err),
+        Err(err) => Ok(Err(err)),
+    }
+}
+
+/// Copies a file to stdout without splitting it into lines. Compressed
+/// inputs go through their decoder. Returns false if the file could not
+/// be opened or read.
+fn copy_plain<W: Write>(
+    filename: &str,
+    args: &Args,
+    out: &mut BufWriter<W>,
+) -> Result<bool> {
+    let copied = match filename {
+        "-" => match open(filename, args) {
+            Ok(mut reader) => copy(&mut reader, out)?
+                .map_err(anyhow::Error::from),
+            Err(err) => Err(err),
+        },
+        _ => {
+            let opened = File::open(filename).and_then(|file| {
+                let is_file = file.metadata()?.is_file();
+                let mut reader =
+                    BufReader::with_capacity(COPY_BUFFER_SIZE, file);
+                reader.fill_buf()?;
+                Ok((reader, is_file))
+            });
+            match opened {
+                Err(err) => Err(err.into()),
+                Ok((reader, _))
+                    if !args.no_decompress
+                        && Compression::detect(reader.buffer()).is_some() =>
+                {
+                    match decompress(reader) {
+                        Ok(mut reader) => {
+                            copy(&mut reader, out)?
+                                .map_err(anyhow::Error::from)
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                // Pipes and devices may block, so they go through `copy`,
+                // which can flush as data arrives
+                Ok((mut reader, true)) => {
+                    copy_file(&mut reader, out)?.map_err(anyhow::Error::from)
+                }
+                Ok((mut reader, false)) => {
+                    copy(&mut reader, out)?
+                        .map_err(anyhow::Error::from)
+                }
+            }
+        }
+    };
+    if let Err(err) = copied {
+        out.flush()?;
+        report(filename, err);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Removes ANSI escape sequences: CSI (`ESC [` ... final byte), OSC
+/// (`ESC ]` ... BEL or `ESC \`) and the other `ESC` sequences. A sequence
+/// cut short by the end of the line never takes the line terminator, a
+/// newline or a carriage return with it.
+fn strip_ansi(line: &[u8], terminator: u8) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(line.len());
+    let mut bytes = line.iter().copied().peekable();
+    let in_sequence =
+        |byte: &u8| !matches!(*byte, b'\n' | b'\r') && *byte != terminator;
+    while let Some(byte) = bytes.next() {
+        if byte != 0x1b {
+            stripped.push(byte);
+            continue;
+        }
+        match bytes.next_if(in_sequence) {
+            Some(b'[') => {
+                // Parameter and intermediate bytes run up to a final byte
+                while let Some(byte) = bytes.next_if(in_sequence) {
+                    if (0x40..=0x7e).contains(&byte) {
+                        break;
+                    }
+                }
+            }
+            Some(b']') => {
+                while let Some(byte) = bytes.next_if(in_sequence) {
+                    if byte == 0x07
+                        || (byte == 0x1b && bytes.next_if_eq(&b'\\').is_some())
+                    {
+                        break;
+                    }
+                }
+            }
+            // nF escapes such as `ESC ( B` have intermediate bytes before
+            // their final byte
+            Some(0x20..=0x2f) => {
+                let intermediate = |byte: &u8| (0x20..=0x2f).contains(byte);
+                while bytes.next_if(intermediate).is_some() {}
+                bytes.next_if(in_sequence);
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Replaces tabs with spaces up to the next multiple of `tab_size`
+/// columns, counting each UTF-8 character as one column.
+fn expand_tabs(line: &[u8], tab_size: usize) -> Vec<u8> {
+    let mut expanded = Vec::with_capacity(line.len());
+    let mut column = 0;
+    for &byte in line {
+        match byte {
+            b'\t' => {
+                let spaces = tab_size - column % tab_size;
+                expanded.resize(expanded.len() + spaces, b' ');
+                column += spaces;
+            }
+            b'\n' | b'\r' => {
+                expanded.push(byte);
+                column = 0;
+            }
+            // UTF-8 continuation bytes don't start a new column
+            0x80..=0xbf => expanded.push(byte),
+            _ => {
+                expanded.push(byte);
+                column += 1;
+            }
+        }
+    }
+    expanded
+}
+
+/// The byte that ends a line, or a record with -z
+fn line_terminator(args: &Args) -> u8 {
+    if args.null_data {
+        b'\0'
+    } else {
+        b'\n'
+    }
+}
+
+/// Writes a line using GNU cat's notation for the enabled -E, -T and -v
+/// options.
+fn write_visible(
+    out: &mut impl Write,
+    line: &[u8],
+    args: &Args,
+) -> io::Result<()> {
+    let terminator = line_terminator(args);
+    let (body, terminated) = match line.strip_suffix(&[terminator]) {
+        Some(body) => (body, true),
+        None => (line, false),
+    };
+    // Like GNU cat, -E shows the CR of a CRLF ending as ^M$ even without -v
+    let (body, carriage_return) = match body.strip_suffix(b"\r") {
+        Some(body) if terminated && !args.null_data && args.show_ends => {
+            (body, true)
+        }
+        _ => (body, false),
+    };
+
+    let mut buf = Vec::with_capacity(line.len() * 2);
+    for &byte in body {
+        match byte {
+            b'\t' if args.show_tabs => buf.extend_from_slice(b"^I"),
+            b'\t' | b'\n' => buf.push(byte),
+            _ if !args.show_nonprinting => buf.push(byte),
+            _ => {
+                let low = if byte >= 128 {
+                    buf.extend_from_slice(b"M-");
+                    byte - 128
+                } else {
+                    byte
+                };
+                match low {
+                    0..=31 => buf.extend_from_slice(&[b'^', low + 64]),
+                    127 => buf.extend_from_slice(b"^?"),
+                    _ => buf.push(low),
+                }
+            }
+        }
+    }
+    if carriage_return {
+        buf.extend_from_slice(b"^M");
+    }
+    if terminated {
+        if args.show_ends {
+            buf.push(b'$');
+        }
+        buf.push(terminator);
+    }
+    out.write_all(&buf)
+}
+
+/// The name shown for an input in prefixes and JSON records
+fn display_name(filename: &str) -> &str {
+    match filename {
+        "-" => "(standard input)",
+        _ => filename,
+    }
+}
+
+/// Writes the `NAME:` prefix of --with-filename.
+fn write_filename(
+    out: &mut impl Write,
+    filename: &str,
+    args: &Args,
+) -> io::Result<()> {
+    let name = display_name(filename);
+    if args.color {
+        write!(
+            out,
+            "{COLOR_FILENAME}{name}{COLOR_RESET}\
+             {COLOR_SEPARATOR}:{COLOR_RESET}"
+        )
+    } else {
+        write!(out, "{name}:")
+    }
+}
+
+/// Writes a line as a --json record, always ending it with a newline.
+fn write_json(
+    out: &mut Vec<u8>,
+    filename: &str,
+    line_num: u64,
+    line: &[u8],
+    terminator: u8,
+) -> Result<()> {
+    let line = line.strip_suffix(&[terminator]).unwrap_or(line);
+    let (text, text_base64) = match std::str::from_utf8(line) {
+        Ok(text) => (Some(text), None),
+        Err(_) => (None, Some(BASE64_STANDARD.encode(line))),
+    };
+    let record = JsonLine {
+        file: display_name(filename),
+        line: line_num,
+        text,
+        text_base64,
+    };
+    serde_json::to_writer(&mut *out, &record)?;
+    out.push(b'\n');
+    Ok(())
+}
+
+fn write_number(
+    out: &mut impl Write,
+    num: i64,
+    args: &Args,
+) -> io::Result<()> {
+    // With --with-filename, numbers take the grep-like NAME:LINE: form
+    if args.with_filename {
+        return if args.color {
+            write!(
+                out,
+                "{COLOR_LINE_NUMBER}{num}{COLOR_RESET}\
+                 {COLOR_SEPARATOR}:{COLOR_RESET}"
+            )
+        } else {
+            write!(out, "{num}:")
+        };
+    }
+
+    let width = args.number_width;
+    let sep = &args.number_separator;
+    match args.number_align {
+        NumberAlign::Left => write!(out, "{num:<width$}{sep}"),
+        NumberAlign::Right => write!(out, "{num:>width$}{sep}"),
+        NumberAlign::Zero => write!(out, "{num:0width$}{sep}"),
+    }
+}
+
+/// Writes one `hexdump -C` row: offset, up to 16 bytes in hex and the
+/// printable ASCII column.
+fn write_hex_row(
+    out: &mut impl Write,
+    offset: u64,
+    row: &[u8],
+) -> io::Result<()> {
+    write!(out, "{offset:08x} ")?;
+    for i in 0..HEX_ROW_SIZE {
+        if i % 8 == 0 {
+            write!(out, " ")?;
+        }
+        match row.get(i) {
+            Some(byte) => write!(out, "{byte:02x} ")?,
+            None => write!(out, "   ")?,
+        }
+    }
+    let ascii: String = row
+        .iter()
+        .map(|&byte| match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        })
+        .collect();
+    writeln!(out, " |{ascii}|")
+}
+
+/// Dumps the concatenated inputs in hex, starting --offset bytes in and
+/// stopping after --length bytes. Returns false if any input failed.
+fn dump_hex(args: &Args, out: &mut impl Write) -> Result<bool> {
+    let mut success = true;
+    let mut to_skip = args.offset;
+    let mut remaining = args.length.unwrap_or(u64::MAX);
+    let mut offset = args.offset;
+    let mut row = Vec::with_capacity(HEX_ROW_SIZE);
+    let mut buffer = [0; 8192];
+    for filename in &args.files {
+        if remaining == 0 {
+            break;
+        }
+        match open(filename, args) {
+            Err(err) => {
+                out.flush()?;
+                report(filename, err);
+                success = false;
+            }
+            Ok(mut file) => {
+                let mut skip = (&mut file).take(to_skip);
+                match io::copy(&mut skip, &mut io::sink()) {
+                    Ok(skipped) => to_skip -= skipped,
+                    Err(err) => {
+                        out.flush()?;
+                        report(filename, err);
+                        success = false;
+                        continue;
+                    }
+                }
+                let mut file = file.take(remaining);
+                loop {
+                    let bytes_read = match file.read(&mut buffer) {
+                        Ok(bytes_read) => bytes_read,
+                        Err(err) => {
+                            out.flush()?;
+                            report(filename, err);
+                            success = false;
+                            break;
+                        }
+                    };
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    remaining -= bytes_read as u64;
+                    for &byte in &buffer[..bytes_read] {
+                        row.push(byte);
+                        if row.len() == HEX_ROW_SIZE {
+                            write_hex_row(out, offset, &row)?;
+                            offset += HEX_ROW_SIZE as u64;
+                            row.clear();
+                        }
+                    }
+                    out.flush()?;
+                }
+            }
+        }
+    }
+    if !row.is_empty() {
+        write_hex_row(out, offset, &row)?;
+        offset += row.len() as u64;
+    }
+    // Like hexdump, end with the offset just past the last byte
+    if offset > args.offset {
+        writeln!(out, "{offset:08x}")?;
+    }
+    out.flush()?;
+    Ok(success)
+}
+
+fn glob_set(globs: &[Glob]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(glob.clone());
+    }
+    Ok(builder.build()?)
+}
+
+/// With -r, replaces each directory argument with the files under it,
+/// sorted by name and filtered by --include, --exclude and --gitignore.
+/// Returns false along with the files if part of a tree was unreadable.
+fn expand_directories(args: &Args) -> Result<(Vec<String>, bool)> {
+    let include = glob_set(&args.include)?;
+    let exclude = glob_set(&args.exclude)?;
+    let mut files = Vec::new();
+    let mut success = true;
+    for filename in &args.files {
+        if !(args.recursive && Path::new(filename).is_dir()) {
+            files.push(filename.clone());
+            continue;
+        }
+
+        let gitignore = args.gitignore;
+        let walker = WalkBuilder::new(filename)
+            .standard_filters(false)
+            .git_ignore(gitignore)
+            .git_exclude(gitignore)
+            .git_global(gitignore)
+            .ignore(gitignore)
+            .parents(gitignore)
+            .require_git(false)
+            .filter_entry(move |entry| {
+                !(gitignore && entry.file_name() == ".git")
+            })
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for entry in walker {
+            match entry {
+                Err(err) => {
+                    eprintln!("catr: {}", error_message(err));
+                    success = false;
+                }
+                Ok(entry) => {
+                    let name = entry.file_name();
+                    if entry.path().is_file()
+                        && (include.is_empty() || include.is_match(name))
+                        && !exclude.is_match(name)
+                    {
+                        let path = entry.path().to_string_lossy();
+                        files.push(path.into_owned());
+                    }
+                }
+            }
+        }
+    }
+    Ok((files, success))
+}
+
+/// The lines printed before a file: the --separator (a blank line by
+/// default with --header) unless it is the first file, then the --header
+/// with its {name}, {size} and {index} placeholders filled in.
+fn preamble(args: &Args, filename: &str, index: usize) -> Vec<u8> {
+    let mut text = String::new();
+    if index > 1 && (args.separator.is_some() || args.header.is_some()) {
+        text.push_str(args.separator.as_deref().unwrap_or_default());
+        text.push('\n');
+    }
+    if let Some(format) = &args.header {
+        let size = match filename {
+            "-" => None,
+            _ => fs::metadata(filename).ok().map(|meta| meta.len()),
+        };
+        let size = size.map_or_else(|| "?".to_string(), |n| n.to_string());
+        // One pass, so a name containing "{size}" is left alone
+        let mut rest = format.as_str();
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let (value, len) = if rest.starts_with("{name}") {
+                // Named like headr's headers, not grep's prefixes
+                let name = match filename {
+                    "-" => "standard input",
+                    _ => filename,
+                };
+                (name.to_string(), 6)
+            } else if rest.starts_with("{size}") {
+                (size.clone(), 6)
+            } else if rest.starts_with("{index}") {
+                (index.to_string(), 7)
+            } else {
+                ("{".to_string(), 1)
+            };
+            text.push_str(&value);
+            rest = &rest[len..];
+        }
+        text.push_str(rest);
+        text.push('\n');
+    }
+    text.into_bytes()
+}
+
+/// Strips the "(os error N)" suffix Rust adds to I/O errors so messages
+/// read like those of the GNU tools.
+fn error_message(err: impl Display) -> String {
+    let message = err.to_string();
+    match message.find(" (os error ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
+
+fn report(filename: &str, err: impl Display) {
+    eprintln!("catr: {filename}: {}", error_message(err));
+}
+
+/// Writes every input to `out` and returns false if any of them failed.
+/// All output goes through this one buffer, flushed before diagnostics so
+/// they appear in order, and after every write with -u.
+fn cat<W: Write>(args: &Args, out: &mut BufWriter<W>) -> Result<bool> {
+    if args.hex {
+        return dump_hex(args, out);
+    }
+
+    let mut success = true;
+
+    if is_plain_copy(args) {
+        for filename in &args.files {
+            success &= copy_plain(filename, args, out)?;
+        }
+        out.flush()?;
+        return Ok(success);
+    }
+
+    // Numbering and blank tracking follow the concatenated stream, so a
+    // line left unterminated by one file is continued by the next. The
+    // next line number is None once it no longer fits in an i64.
+    let mut line_num = Some(args.number_start);
+    let mut at_line_start = true;
+    let mut prev_blank = false;
+    let terminator = line_terminator(args);
+    let mut selector = (!args.line_ranges.is_empty())
+        .then(|| LineSelector::new(&args.line_ranges));
+    let transcoding = is_transcoding(args);
+    let output_encoding = args.to_encoding.unwrap_or(UTF_8);
+    let keep_bom = !args.strip_bom
+        && [UTF_8, UTF_16LE, UTF_16BE].contains(&output_encoding);
+    let mut opened_files = 0;
+    for (file_num, filename) in args.files.iter().enumerate() {
+        // Only the last input is followed, and only if it is a regular file
+        let follower = (args.follow
+            && file_num + 1 == args.files.len()
+            && fs::metadata(filename).is_ok_and(|meta| meta.is_file()))
+        .then(|| Follower::new(filename));
+        let opened = open(filename, args).and_then(|file| {
+            if transcoding {
+                decode(file, args)
+            } else {
+                Ok((file, false))
+            }
+        });
+        match opened {
+            Err(err) => {
+                out.flush()?;
+                report(filename, err);
+                success = false;
+            }
+            Ok((file, mut has_bom)) => {
+                // Our own buffer shows whether the next read may block
+                let mut file = BufReader::new(file);
+                // NAME:LINE: prefixes give each line's place in its file
+                if args.number_per_file || args.with_filename {
+                    line_num = Some(args.number_start);
+                }
+                // JSON records, headers and NAME: prefixes never span files
+                if args.number_per_file
+                    || args.json
+                    || args.header.is_some()
+                    || args.with_filename
+                {
+                    at_line_start = true;
+                }
+                opened_files += 1;
+                let mut file_header = preamble(args, filename, opened_files);
+                if !file_header.is_empty() {
+                    if output_encoding != UTF_8 {
+                        match encode(&file_header, output_encoding) {
+                            Ok(encoded) => file_header = encoded,
+                            Err(err) => {
+                                out.flush()?;
+                                report(filename, err);
+                                success = false;
+                                continue;
+                            }
+                        }
+                    }
+                    match selector.as_mut() {
+                        Some(selector) => {
+                            selector.push_header(out, file_header)?
+                        }
+                        None => out.write_all(&file_header)?,
+                    }
+                }
+                let mut file_line = 0;
+                let mut line = Vec::new();
+                let mut rendered = Vec::new();
+                // Lines are kept as raw bytes, terminator included, so the
+                // output is a byte-exact copy of the input
+                loop {
+                    line.clear();
+                    // Flush before waiting on a pipe, so lines that already
+                    // arrived are not held back until more input comes
+                    if !file.buffer().contains(&terminator) {
+                        out.flush()?;
+                    }
+                    let read = file.read_until(terminator, &mut line);
+                    let bytes_read = match read {
+                        Ok(bytes_read) => bytes_read,
+                        Err(err) => {
+                            out.flush()?;
+                            report(filename, err);
+                            success = false;
+                            break;
+                        }
+                    };
+                    if bytes_read == 0 {
+                        match &follower {
+                            Some(follower) => {
+                                out.flush()?;
+                                follower.wait();
+                                continue;
                             }
+                            None => break,
+                        }
+                    }
+                    if args.strip_ansi {
+                        line = strip_ansi(&line, terminator);
+                    }
+                    if let Some(tab_size) = args.expand_tabs {
+                        line = expand_tabs(&line, tab_size);
+                    }
+                    if line.is_empty() {
+                        continue;
+                    }
+
+                    let line_start = at_line_start;
+                    at_line_start = line.ends_with(&[terminator]);
+                    if line_start {
+                        file_line += 1;
+                    }
+                    let blank = line_start && line == [terminator];
+                    if args.squeeze_blank && blank && prev_blank {
+                        continue;
+                    }
+                    prev_blank = blank;
+
+                    rendered.clear();
+                    if args.json {
+                        write_json(
+                            &mut rendered,
+                            filename,
+                            file_line,
+                            &line,
+                            terminator,
+                        )?;
+                    } else {
+                        // A kept BOM goes in front of numbers and prefixes
+                        if std::mem::take(&mut has_bom) && keep_bom {
+                            rendered.extend_from_slice(BOM);
+                        }
+                        if line_start && args.with_filename {
+                            write_filename(&mut rendered, filename, args)?;
+                        }
+                        if line_start
+                            && (args.number_lines
+                                || (args.number_nonblank_lines && !blank))
+                        {
+                            let num = line_num.ok_or_else(|| {
+                                anyhow!(
+                                    "{}: line number out of range",
+                                    display_name(filename)
+                                )
+                            })?;
+                            write_number(&mut rendered, num, args)?;
+                            line_num = num.checked_add(args.number_increment);
+                        }
+                        if args.show_ends
+                            || args.show_tabs
+                            || args.show_nonprinting
+                        {
+                            write_visible(&mut rendered, &line, args)?;
                         } else {
-                            format!("{line_read}")
-                        };
-                    println!("{}", line);
+                            rendered.extend_from_slice(&line);
+                        }
+                    }
+                    if output_encoding != UTF_8 {
+                        match encode(&rendered, output_encoding) {
+                            Ok(encoded) => rendered = encoded,
+                            Err(err) => {
+                                out.flush()?;
+                                report(filename, err);
+                                success = false;
+                                break;
+                            }
+                        }
+                    }
+
+                    match selector.as_mut() {
+                        Some(selector) => selector.push(
+                            out,
+                            line_start,
+                            std::mem::take(&mut rendered),
+                        )?,
+                        None => out.write_all(&rendered)?,
+                    }
+                    if args.unbuffered {
+                        out.flush()?;
+                    }
+                }
+
+                // Like grep, end a last line left unterminated so the next
+                // file's prefix starts a line of its own
+                if args.with_filename && !at_line_start {
+                    let mut end = vec![terminator];
+                    if output_encoding != UTF_8 {
+                        end = encode(&end, output_encoding)?;
+                    }
+                    match selector.as_mut() {
+                        Some(selector) => selector.push(out, false, end)?,
+                        None => out.write_all(&end)?,
+                    }
+                    at_line_start = true;
+                }
+
+                if args.number_per_file {
+                    if let Some(selector) = selector.as_mut() {
+                        selector.finish(out)?;
+                    }
+                    if args.unbuffered {
+                        out.flush()?;
+                    }
                 }
             }
         }
     }
-    Ok(())
+    if let Some(selector) = selector.as_mut() {
+        selector.finish(out)?;
+    }
+    out.flush()?;
+    Ok(success)
+}
+
+/// The device and inode numbers of a regular file.
+fn file_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    meta.is_file().then(|| (meta.dev(), meta.ino()))
+}
+
+/// Identifies the file behind stdin or stdout, if it is a regular file.
+fn stream_id(fd: BorrowedFd) -> Option<(u64, u64)> {
+    let file = File::from(fd.try_clone_to_owned().ok()?);
+    file_id(&file.metadata().ok()?)
+}
+
+fn input_id(filename: &str) -> Option<(u64, u64)> {
+    match filename {
+        "-" => stream_id(io::stdin().as_fd()),
+        _ => file_id(&fs::metadata(filename).ok()?),
+    }
+}
+
+/// Checks that no input is also the output, as reading it would lose data
+/// or never end. An -o FILE among the inputs is fatal, so FILE is left
+/// alone; inputs that are the file behind stdout are dropped like GNU cat
+/// does. Returns false if any were dropped.
+fn check_output_files(args: &mut Args) -> Result<bool> {
+    if let Some(output_id) = args.output.as_deref().and_then(input_id) {
+        let mut inputs = args.files.iter();
+        if let Some(filename) =
+            inputs.find(|filename| input_id(filename) == Some(output_id))
+        {
+            return Err(anyhow!("{filename}: input file is output file"));
+        }
+    }
+    if args.output.is_some() && !args.tee {
+        return Ok(true);
+    }
+
+    let Some(stdout_id) = stream_id(io::stdout().as_fd()) else {
+        return Ok(true);
+    };
+    let mut success = true;
+    args.files.retain(|filename| {
+        let is_output = input_id(filename) == Some(stdout_id);
+        if is_output {
+            report(filename, "input file is output file");
+            success = false;
+        }
+        !is_output
+    });
+    Ok(success)
+}
+
+/// Writes the output of `cat` to a temporary file beside -o FILE and
+/// renames it over FILE, so FILE is never left half written. A symlinked
+/// FILE is resolved first so the link's target gets the output, and a
+/// hard-linked one is copied over in place so its links stay shared.
+fn write_output(args: &Args, path: &str) -> Result<bool> {
+    let path = Path::new(path);
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = target.file_name().unwrap_or(target.as_os_str());
+    let temp_path = dir.join(format!(
+        ".{}.catr-{}",
+        name.to_string_lossy(),
+        process::id()
+    ));
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .map_err(|err| {
+            anyhow!("{}: {}", path.display(), error_message(err))
+        })?;
+
+    let written = (|| {
+        // An existing file keeps its permissions
+        let meta = fs::metadata(&target).ok();
+        if let Some(meta) = &meta {
+            file.set_permissions(meta.permissions())?;
+        }
+        let success = if args.tee {
+            let tee = Tee {
+                file: &file,
+                stdout: io::stdout().lock(),
+            };
+            cat(args, &mut BufWriter::new(tee))?
+        } else {
+            cat(args, &mut BufWriter::new(&file))?
+        };
+        file.sync_all()?;
+        let replaced = match meta {
+            Some(meta) if meta.nlink() > 1 => {
+                fs::copy(&temp_path, &target).and_then(|_| {
+                    File::open(&target)?.sync_all()?;
+                    fs::remove_file(&temp_path)
+                })
+            }
+            _ => fs::rename(&temp_path, &target),
+        };
+        replaced.map_err(|err| {
+            anyhow!("{}: {}", path.display(), error_message(err))
+        })?;
+        Ok(success)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+/// Processes every input and returns false if any of them failed.
+fn run(mut args: Args) -> Result<bool> {
+    let (files, mut success) = expand_directories(&args)?;
+    args.files = files;
+    success &= check_output_files(&mut args)?;
+
+    success &= match &args.output {
+        Some(path) => write_output(&args, path)?,
+        None => cat(&args, &mut BufWriter::new(io::stdout().lock()))?,
+    };
+    Ok(success)
 }
 
 fn main() {
-    if let Err(err) = run(get_args()) {
-        eprintln!("{err}");
-        std::process::exit(1);
+    match run(get_args()) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("catr: {}", error_message(err));
+            std::process::exit(1);
+        }
     }
 }
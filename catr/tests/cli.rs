@@ -1,15 +1,40 @@
 use anyhow::Result;
-use assert_cmd::Command;
+use assert_cmd::{cargo::cargo_bin, Command};
 use predicates::prelude::*;
 use pretty_assertions::assert_eq;
 use rand::{distributions::Alphanumeric, Rng};
 use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::Duration;
 
 const PRG: &str = "catr";
 const EMPTY: &str = "tests/inputs/empty.txt";
 const FOX: &str = "tests/inputs/fox.txt";
 const SPIDERS: &str = "tests/inputs/spiders.txt";
 const BUSTLE: &str = "tests/inputs/the-bustle.txt";
+const CRLF: &str = "tests/inputs/crlf.txt";
+const LATIN1: &str = "tests/inputs/latin1.txt";
+const CONTROL: &str = "tests/inputs/control.txt";
+const BLANKS: &str = "tests/inputs/blanks.txt";
+const FOX_GZ: &str = "tests/inputs/fox.txt.gz";
+const CORRUPT_GZ: &str = "tests/inputs/corrupt.gz";
+const TRUNCATED_GZ: &str = "tests/inputs/truncated.gz";
+const SPIDERS_BZ2: &str = "tests/inputs/spiders.txt.bz2";
+const BUSTLE_XZ: &str = "tests/inputs/the-bustle.txt.xz";
+const BUSTLE_ZST: &str = "tests/inputs/the-bustle.txt.zst";
+const MULTI_GZ: &str = "tests/inputs/multi.gz";
+const UTF16LE_BOM: &str = "tests/inputs/utf16le-bom.txt";
+const LATIN1_TEXT: &str = "tests/inputs/latin1-text.txt";
+const SHIFT_JIS: &str = "tests/inputs/shift-jis.txt";
+const RECORDS: &str = "tests/inputs/records.bin";
+const CI_LOG: &str = "tests/inputs/ci-log.txt";
+const TRUNCATED_ANSI: &str = "tests/inputs/truncated-ansi.txt";
+const TABS: &str = "tests/inputs/tabs.txt";
+const TREE: &str = "tests/inputs/tree";
+const MAGIC_TEXT: &str = "tests/inputs/magic-text.txt";
 
 #[test]
 fn usage() -> Result<()> {
@@ -36,15 +61,69 @@ fn gen_bad_file() -> String {
     }
 }
 
+// A path in the temp directory that does not exist yet
+fn temp_path() -> PathBuf {
+    loop {
+        let name = format!("catr-{}", gen_bad_file());
+        let path = std::env::temp_dir().join(name);
+        if fs::symlink_metadata(&path).is_err() {
+            return path;
+        }
+    }
+}
+
 #[test]
 fn skips_bad_file() -> Result<()> {
     let bad = gen_bad_file();
-    let expected = format!("{bad}: .* [(]os error 2[)]");
+    let expected = format!("catr: {bad}: No such file or directory\n");
+    for args in [vec![&bad, FOX], vec!["-n", &bad, FOX]] {
+        Command::cargo_bin(PRG)?
+            .args(&args)
+            .assert()
+            .code(1)
+            .stdout(predicate::str::contains("The quick brown fox"))
+            .stderr(expected.clone());
+    }
+    Ok(())
+}
+
+#[test]
+fn skips_unreadable_file() -> Result<()> {
+    let expected = format!("catr: {TRUNCATED_GZ}: incomplete deflate stream\n");
+    for args in [vec![TRUNCATED_GZ, FOX], vec!["-n", TRUNCATED_GZ, FOX]] {
+        Command::cargo_bin(PRG)?
+            .args(&args)
+            .assert()
+            .code(1)
+            .stdout(predicate::str::ends_with(
+                "The quick brown fox jumps over the lazy dog.\n",
+            ))
+            .stderr(expected.clone());
+    }
+    Ok(())
+}
+
+#[test]
+fn hex_skips_unreadable_file() -> Result<()> {
     Command::cargo_bin(PRG)?
-        .arg(&bad)
+        .args(["-x", "--decompress", TRUNCATED_GZ, FOX])
         .assert()
-        .success()
-        .stderr(predicate::str::is_match(expected)?);
+        .code(1)
+        .stdout(predicate::str::contains("|r the lazy dog..|"))
+        .stderr(format!("catr: {TRUNCATED_GZ}: incomplete deflate stream\n"));
+    Ok(())
+}
+
+#[test]
+fn skips_directory() -> Result<()> {
+    for args in [vec!["tests", FOX], vec!["-n", "tests", FOX]] {
+        Command::cargo_bin(PRG)?
+            .args(&args)
+            .assert()
+            .code(1)
+            .stdout(predicate::str::contains("The quick brown fox"))
+            .stderr("catr: tests: Is a directory\n");
+    }
     Ok(())
 }
 
@@ -78,6 +157,32 @@ fn run_stdin(
     Ok(())
 }
 
+fn run_bytes(args: &[&str], expected_file: &str) -> Result<()> {
+    let expected = fs::read(expected_file)?;
+    let output = Command::cargo_bin(PRG)?.args(args).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+
+    Ok(())
+}
+
+fn run_stdin_bytes(
+    input_file: &str,
+    args: &[&str],
+    expected_file: &str,
+) -> Result<()> {
+    let input = fs::read(input_file)?;
+    let expected = fs::read(expected_file)?;
+    let output = Command::cargo_bin(PRG)?
+        .write_stdin(input)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+    Ok(())
+}
+
 #[test]
 fn bustle_stdin() -> Result<()> {
     run_stdin(BUSTLE, &["-"], "tests/expected/the-bustle.txt.stdin.out")
@@ -178,3 +283,919 @@ fn all_n() -> Result<()> {
 fn all_b() -> Result<()> {
     run(&[FOX, SPIDERS, BUSTLE, "-b"], "tests/expected/all.b.out")
 }
+
+#[test]
+fn all_n_per_file() -> Result<()> {
+    run(
+        &[FOX, SPIDERS, BUSTLE, "-n", "--number-per-file"],
+        "tests/expected/all.n.per-file.out",
+    )
+}
+
+#[test]
+fn all_b_per_file() -> Result<()> {
+    run(
+        &[FOX, SPIDERS, BUSTLE, "-b", "--number-per-file"],
+        "tests/expected/all.b.per-file.out",
+    )
+}
+
+#[test]
+fn unterminated_line_continues_n() -> Result<()> {
+    run_bytes(&["-n", CRLF, LATIN1], "tests/expected/crlf-latin1.n.out")
+}
+
+#[test]
+fn unterminated_line_continues_b() -> Result<()> {
+    run_bytes(&["-b", CRLF, BLANKS], "tests/expected/crlf-blanks.b.out")
+}
+
+#[test]
+fn crlf() -> Result<()> {
+    run_bytes(&[CRLF], "tests/expected/crlf.txt.out")
+}
+
+#[test]
+fn crlf_n() -> Result<()> {
+    run_bytes(&["-n", CRLF], "tests/expected/crlf.txt.n.out")
+}
+
+#[test]
+fn crlf_b() -> Result<()> {
+    run_bytes(&["-b", CRLF], "tests/expected/crlf.txt.b.out")
+}
+
+#[test]
+fn latin1() -> Result<()> {
+    run_bytes(&[LATIN1], "tests/expected/latin1.txt.out")
+}
+
+#[test]
+fn latin1_n() -> Result<()> {
+    run_bytes(&["-n", LATIN1], "tests/expected/latin1.txt.n.out")
+}
+
+#[test]
+fn latin1_b() -> Result<()> {
+    run_bytes(&["-b", LATIN1], "tests/expected/latin1.txt.b.out")
+}
+
+#[test]
+fn latin1_stdin_n() -> Result<()> {
+    run_stdin_bytes(LATIN1, &["-n", "-"], "tests/expected/latin1.txt.n.out")
+}
+
+#[test]
+fn control_show_all() -> Result<()> {
+    run_bytes(&["-A", CONTROL], "tests/expected/control.txt.A.out")?;
+    run_bytes(&["--show-all", CONTROL], "tests/expected/control.txt.A.out")
+}
+
+#[test]
+fn control_show_nonprinting() -> Result<()> {
+    run_bytes(&["-v", CONTROL], "tests/expected/control.txt.v.out")
+}
+
+#[test]
+fn control_show_ends() -> Result<()> {
+    run_bytes(&["-E", CONTROL], "tests/expected/control.txt.E.out")
+}
+
+#[test]
+fn control_show_tabs() -> Result<()> {
+    run_bytes(&["-T", CONTROL], "tests/expected/control.txt.T.out")
+}
+
+#[test]
+fn control_e() -> Result<()> {
+    run_bytes(&["-e", CONTROL], "tests/expected/control.txt.e.out")?;
+    run_bytes(&["-vE", CONTROL], "tests/expected/control.txt.e.out")
+}
+
+#[test]
+fn control_t() -> Result<()> {
+    run_bytes(&["-t", CONTROL], "tests/expected/control.txt.t.out")?;
+    run_bytes(&["-vT", CONTROL], "tests/expected/control.txt.t.out")
+}
+
+#[test]
+fn control_n_show_all() -> Result<()> {
+    run_bytes(&["-nA", CONTROL], "tests/expected/control.txt.nA.out")
+}
+
+#[test]
+fn control_b_show_all() -> Result<()> {
+    run_bytes(&["-bA", CONTROL], "tests/expected/control.txt.bA.out")
+}
+
+#[test]
+fn blanks_s() -> Result<()> {
+    run(&["-s", BLANKS], "tests/expected/blanks.txt.s.out")?;
+    run(&["--squeeze-blank", BLANKS], "tests/expected/blanks.txt.s.out")
+}
+
+#[test]
+fn blanks_sn() -> Result<()> {
+    run(&["-sn", BLANKS], "tests/expected/blanks.txt.sn.out")
+}
+
+#[test]
+fn blanks_sb() -> Result<()> {
+    run(&["-sb", BLANKS], "tests/expected/blanks.txt.sb.out")
+}
+
+#[test]
+fn blanks_s_across_files() -> Result<()> {
+    run(&["-s", BLANKS, BLANKS], "tests/expected/blanks.txt.twice.s.out")
+}
+
+#[test]
+fn blanks_sn_across_files() -> Result<()> {
+    run(
+        &["-sn", BLANKS, BLANKS],
+        "tests/expected/blanks.txt.twice.sn.out",
+    )
+}
+
+#[test]
+fn bustle_number_width_separator() -> Result<()> {
+    run(
+        &["-n", "--number-width", "3", "--number-separator", ": ", BUSTLE],
+        "tests/expected/the-bustle.txt.n.w3.out",
+    )
+}
+
+#[test]
+fn bustle_number_align_left() -> Result<()> {
+    run(
+        &[
+            "-n",
+            "--number-align",
+            "left",
+            "--number-width",
+            "4",
+            "--number-separator",
+            " | ",
+            BUSTLE,
+        ],
+        "tests/expected/the-bustle.txt.n.left.out",
+    )
+}
+
+#[test]
+fn bustle_number_zero_start_increment() -> Result<()> {
+    run(
+        &[
+            "-n",
+            "--number-align",
+            "zero",
+            "--number-start",
+            "100",
+            "--number-increment",
+            "10",
+            BUSTLE,
+        ],
+        "tests/expected/the-bustle.txt.n.zero.out",
+    )
+}
+
+#[test]
+fn dies_line_number_overflow() -> Result<()> {
+    let max = i64::MAX.to_string();
+    Command::cargo_bin(PRG)?
+        .args(["-n", "--number-start", &max, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{max}\t{}", fs::read_to_string(FOX)?));
+    Command::cargo_bin(PRG)?
+        .args(["-n", "--number-increment", &max, SPIDERS])
+        .assert()
+        .code(1)
+        .stderr(format!("catr: {SPIDERS}: line number out of range\n"));
+    Ok(())
+}
+
+#[test]
+fn dies_bad_number_align() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "--number-align", "center", BUSTLE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid value 'center' for '--number-align <ALIGN>'",
+        ));
+    Ok(())
+}
+
+#[test]
+fn plain_copy_file_and_stdin() -> Result<()> {
+    run_stdin_bytes(LATIN1, &[CRLF, "-"], "tests/expected/crlf-latin1.out")
+}
+
+#[test]
+fn gzip() -> Result<()> {
+    run(&[FOX_GZ], "tests/expected/fox.txt.out")
+}
+
+#[test]
+fn bzip2() -> Result<()> {
+    run(&[SPIDERS_BZ2], "tests/expected/spiders.txt.out")
+}
+
+#[test]
+fn xz() -> Result<()> {
+    run(&[BUSTLE_XZ], "tests/expected/the-bustle.txt.out")
+}
+
+#[test]
+fn zstd() -> Result<()> {
+    run(&["-b", BUSTLE_ZST], "tests/expected/the-bustle.txt.b.out")
+}
+
+#[test]
+fn gzip_multiple_members() -> Result<()> {
+    run(&[MULTI_GZ], "tests/expected/multi.gz.out")
+}
+
+#[test]
+fn compressed_mixed_n() -> Result<()> {
+    run(&["-n", FOX, SPIDERS_BZ2, BUSTLE_XZ], "tests/expected/all.n.out")
+}
+
+#[test]
+fn corrupt_compressed_input() -> Result<()> {
+    let expected = format!("catr: {CORRUPT_GZ}: corrupt deflate stream\n");
+    for args in [vec![CORRUPT_GZ, FOX], vec!["-n", CORRUPT_GZ, FOX]] {
+        Command::cargo_bin(PRG)?
+            .args(&args)
+            .assert()
+            .code(1)
+            .stdout(predicate::str::contains("The quick brown fox"))
+            .stderr(expected.clone());
+    }
+    Command::cargo_bin(PRG)?
+        .args(["--decompress", "-", FOX])
+        .pipe_stdin(CORRUPT_GZ)?
+        .assert()
+        .code(1)
+        .stdout(fs::read_to_string(FOX)?)
+        .stderr("catr: -: corrupt deflate stream\n");
+    Ok(())
+}
+
+#[test]
+fn text_starting_with_magic() -> Result<()> {
+    run(&[MAGIC_TEXT], MAGIC_TEXT)?;
+    run_bytes(&["-v", MAGIC_TEXT], MAGIC_TEXT)
+}
+
+#[test]
+fn no_decompress() -> Result<()> {
+    run_bytes(&["--no-decompress", FOX_GZ], FOX_GZ)?;
+    run_bytes(&["--no-decompress", SPIDERS_BZ2], SPIDERS_BZ2)?;
+    Command::cargo_bin(PRG)?
+        .args(["--decompress", "--no-decompress", "-"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn compressed_stdin() -> Result<()> {
+    run_stdin_bytes(FOX_GZ, &["-"], FOX_GZ)?;
+    run_stdin_bytes(
+        BUSTLE_ZST,
+        &["--decompress", "-n", "-"],
+        "tests/expected/the-bustle.txt.n.stdin.out",
+    )?;
+    run_stdin_bytes(
+        FOX_GZ,
+        &["--decompress", "-"],
+        "tests/expected/fox.txt.out",
+    )
+}
+
+#[test]
+fn bustle_lines() -> Result<()> {
+    run(
+        &["--lines", "2:4", BUSTLE],
+        "tests/expected/the-bustle.txt.lines2-4.out",
+    )
+}
+
+#[test]
+fn bustle_lines_n() -> Result<()> {
+    run(
+        &["-n", "--lines", "2:4", BUSTLE],
+        "tests/expected/the-bustle.txt.n.lines2-4.out",
+    )
+}
+
+#[test]
+fn bustle_lines_from_end() -> Result<()> {
+    run(
+        &["--lines", "-3:", BUSTLE],
+        "tests/expected/the-bustle.txt.lines-3.out",
+    )
+}
+
+#[test]
+fn bustle_lines_multiple_ranges() -> Result<()> {
+    run(
+        &["--lines", ":2,-2:", BUSTLE],
+        "tests/expected/the-bustle.txt.lines-head-tail.out",
+    )?;
+    run(
+        &["--lines", ":2", "--lines=-2:", BUSTLE],
+        "tests/expected/the-bustle.txt.lines-head-tail.out",
+    )
+}
+
+#[test]
+fn all_lines_n() -> Result<()> {
+    run(
+        &["-n", "--lines", "3:5", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.n.lines3-5.out",
+    )?;
+    run(
+        &["-n", "--lines=-1:", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.n.lines-1.out",
+    )
+}
+
+#[test]
+fn all_lines_per_file() -> Result<()> {
+    run(
+        &["-n", "--number-per-file", "--lines=-1:", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.n.per-file.lines-1.out",
+    )
+}
+
+#[test]
+fn dies_bad_lines() -> Result<()> {
+    for (range, msg) in [
+        ("0:3", "line numbers start at 1"),
+        ("a:3", "invalid line number 'a'"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(["--lines", range, BUSTLE])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(msg));
+    }
+    Ok(())
+}
+
+#[test]
+fn dies_follow_negative_lines() -> Result<()> {
+    for range in ["-5:", "1:-1"] {
+        Command::cargo_bin(PRG)?
+            .args(["-f", "--lines", range, BUSTLE])
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains(
+                "negative --lines bounds cannot be used with '--follow'",
+            ));
+    }
+    Ok(())
+}
+
+#[test]
+fn all_with_filename() -> Result<()> {
+    run(&["-H", FOX, SPIDERS, BUSTLE], "tests/expected/all.H.out")?;
+    run(
+        &["--with-filename", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.H.out",
+    )
+}
+
+#[test]
+fn all_with_filename_n() -> Result<()> {
+    run(
+        &["-Hn", "--number-per-file", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.Hn.per-file.out",
+    )
+}
+
+#[test]
+fn all_with_filename_n_numbers_each_file() -> Result<()> {
+    run(
+        &["-Hn", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.Hn.per-file.out",
+    )
+}
+
+#[test]
+fn with_filename_ends_unterminated_line() -> Result<()> {
+    run(&["-H", CRLF, FOX], "tests/expected/crlf.fox.H.out")?;
+    run(&["-Hn", CRLF, FOX], "tests/expected/crlf.fox.Hn.out")
+}
+
+#[test]
+fn fox_stdin_with_filename_color() -> Result<()> {
+    run_stdin(
+        FOX,
+        &["-Hn", "--color", "-"],
+        "tests/expected/fox.txt.Hn.color.stdin.out",
+    )?;
+    run_stdin(
+        FOX,
+        &["-H", "--color=never", "-"],
+        "tests/expected/fox.txt.H.stdin.out",
+    )
+}
+
+#[test]
+fn utf16_bom_to_utf8() -> Result<()> {
+    run_bytes(
+        &["-n", "--to-encoding", "utf-8", UTF16LE_BOM],
+        "tests/expected/utf16le-bom.txt.n.utf8.out",
+    )
+}
+
+#[test]
+fn utf16_bom_strip_bom() -> Result<()> {
+    run_bytes(
+        &["-n", "--strip-bom", UTF16LE_BOM],
+        "tests/expected/utf16le-bom.txt.n.strip-bom.out",
+    )
+}
+
+#[test]
+fn utf16_bom_to_utf16() -> Result<()> {
+    run_bytes(
+        &["-n", "--to-encoding", "utf-16le", UTF16LE_BOM],
+        "tests/expected/utf16le-bom.txt.n.utf16le.out",
+    )
+}
+
+#[test]
+fn latin1_from_encoding() -> Result<()> {
+    run(
+        &["-n", "--from-encoding", "latin1", LATIN1_TEXT],
+        "tests/expected/latin1-text.txt.n.out",
+    )
+}
+
+#[test]
+fn shift_jis_from_encoding() -> Result<()> {
+    run(
+        &["--from-encoding", "shift_jis", SHIFT_JIS],
+        "tests/expected/shift-jis.txt.out",
+    )?;
+    run_bytes(
+        &[
+            "-n",
+            "--from-encoding",
+            "shift_jis",
+            "--to-encoding",
+            "shift_jis",
+            SHIFT_JIS,
+        ],
+        "tests/expected/shift-jis.txt.n.out",
+    )
+}
+
+#[test]
+fn unencodable_character() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--to-encoding", "latin1", "-", FOX])
+        .write_stdin("a\u{1f600}\n")
+        .assert()
+        .code(1)
+        .stdout(fs::read_to_string(FOX)?)
+        .stderr(
+            "catr: -: cannot encode '\u{1f600}' (U+1F600) as windows-1252\n",
+        );
+    Ok(())
+}
+
+#[test]
+fn dies_bad_encoding() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--from-encoding", "klingon", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown encoding 'klingon'"));
+    Ok(())
+}
+
+#[test]
+fn records_null_data() -> Result<()> {
+    run_bytes(&["-z", RECORDS], RECORDS)?;
+    run_bytes(&["-zn", RECORDS], "tests/expected/records.bin.zn.out")?;
+    run_bytes(
+        &["--null-data", "-b", RECORDS],
+        "tests/expected/records.bin.zb.out",
+    )
+}
+
+#[test]
+fn records_null_data_squeeze() -> Result<()> {
+    run_bytes(&["-zs", RECORDS], "tests/expected/records.bin.zs.out")
+}
+
+#[test]
+fn records_null_data_show_all() -> Result<()> {
+    run_bytes(&["-zA", RECORDS], "tests/expected/records.bin.zA.out")
+}
+
+#[test]
+fn records_null_data_stdin() -> Result<()> {
+    run_stdin_bytes(
+        RECORDS,
+        &["-zn", "-"],
+        "tests/expected/records.bin.zn.out",
+    )
+}
+
+#[test]
+fn strip_ansi_keeps_terminators() -> Result<()> {
+    run(
+        &["-n", "--strip-ansi", TRUNCATED_ANSI],
+        "tests/expected/truncated-ansi.txt.n.strip-ansi.out",
+    )
+}
+
+#[test]
+fn ci_log_strip_ansi() -> Result<()> {
+    run(
+        &["--strip-ansi", CI_LOG],
+        "tests/expected/ci-log.txt.strip-ansi.out",
+    )
+}
+
+#[test]
+fn ci_log_strip_ansi_expand_tabs() -> Result<()> {
+    run(
+        &["--strip-ansi", "--expand-tabs", CI_LOG],
+        "tests/expected/ci-log.txt.strip-ansi.expand.out",
+    )?;
+    run(
+        &["-b", "--strip-ansi", "--expand-tabs=4", CI_LOG],
+        "tests/expected/ci-log.txt.strip-ansi.expand4.b.out",
+    )
+}
+
+#[test]
+fn tabs_expand_tabs() -> Result<()> {
+    run(&["--expand-tabs=3", TABS], "tests/expected/tabs.txt.expand3.out")
+}
+
+#[test]
+fn dies_bad_expand_tabs() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--expand-tabs=0", TABS])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid value '0' for '--expand-tabs[=<N>]'",
+        ));
+    Ok(())
+}
+
+#[test]
+fn json() -> Result<()> {
+    run(&["--json", FOX, BUSTLE], "tests/expected/fox-bustle.json.out")
+}
+
+#[test]
+fn json_unterminated_line() -> Result<()> {
+    run(&["--json", CRLF, FOX], "tests/expected/crlf-fox.json.out")
+}
+
+#[test]
+fn json_non_utf8_stdin() -> Result<()> {
+    run_stdin_bytes(
+        LATIN1,
+        &["--json", "-"],
+        "tests/expected/latin1.txt.json.stdin.out",
+    )
+}
+
+#[test]
+fn dies_json_and_number() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--json", "-n", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "the argument '--json' cannot be used with '--number'",
+        ));
+    Ok(())
+}
+
+#[test]
+fn hex() -> Result<()> {
+    run(&["-x", FOX], "tests/expected/fox.txt.x.out")?;
+    run(&["--hex", "--decompress", FOX_GZ], "tests/expected/fox.txt.x.out")?;
+    run(&["--hex", FOX_GZ], "tests/expected/fox.txt.gz.x.out")
+}
+
+#[test]
+fn hex_multiple_files() -> Result<()> {
+    run(
+        &["-x", FOX, SPIDERS, LATIN1],
+        "tests/expected/fox-spiders-latin1.x.out",
+    )
+}
+
+#[test]
+fn hex_offset_length() -> Result<()> {
+    run(
+        &["-x", "--offset", "40", "--length", "20", FOX, SPIDERS, LATIN1],
+        "tests/expected/fox-spiders-latin1.x.offset40.length20.out",
+    )
+}
+
+#[test]
+fn hex_empty() -> Result<()> {
+    run(&["-x", EMPTY], "tests/expected/empty.txt.out")
+}
+
+#[test]
+fn dies_hex_and_encoding() -> Result<()> {
+    for args in [
+        vec!["-x", "--to-encoding", "utf-16le", FOX],
+        vec!["-x", "--from-encoding", "latin1", FOX],
+        vec!["-x", "--strip-bom", FOX],
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(&args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+    Ok(())
+}
+
+#[test]
+fn dies_offset_without_hex() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--offset", "4", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "the following required arguments were not provided",
+        ));
+    Ok(())
+}
+
+// Writes one line to catr's stdin and expects it back on stdout while
+// stdin is still open
+fn echoes_before_eof(
+    args: &[&str],
+    input: &str,
+    expected: &str,
+) -> Result<()> {
+    let mut child = std::process::Command::new(cargo_bin(PRG))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    stdin.write_all(input.as_bytes())?;
+    stdin.flush()?;
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        BufReader::new(stdout).read_line(&mut line).ok();
+        tx.send(line).ok();
+    });
+    let received = rx.recv_timeout(Duration::from_secs(5));
+    drop(stdin);
+    child.wait()?;
+    assert_eq!(received?, expected);
+    Ok(())
+}
+
+#[test]
+fn unbuffered() -> Result<()> {
+    echoes_before_eof(&["-u"], "streamed\n", "streamed\n")?;
+    echoes_before_eof(&["-u", "-n"], "streamed\n", "     1\tstreamed\n")
+}
+
+#[test]
+fn streams_without_unbuffered() -> Result<()> {
+    echoes_before_eof(&[], "streamed\n", "streamed\n")?;
+    echoes_before_eof(&["-n"], "streamed\n", "     1\tstreamed\n")
+}
+
+#[test]
+fn follow() -> Result<()> {
+    let path = temp_path();
+    fs::write(&path, "one\n")?;
+    let mut child = std::process::Command::new(cargo_bin(PRG))
+        .args(["-n", "-f", FOX, path.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let timeout = Duration::from_secs(5);
+    let received = (|| -> Result<Vec<String>> {
+        let mut lines = vec![rx.recv_timeout(timeout)?];
+        lines.push(rx.recv_timeout(timeout)?);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"two\n")?;
+        lines.push(rx.recv_timeout(timeout)?);
+        Ok(lines)
+    })();
+
+    // catr never exits on its own with -f
+    child.kill()?;
+    child.wait()?;
+    fs::remove_file(&path)?;
+    assert_eq!(
+        received?,
+        [
+            "     1\tThe quick brown fox jumps over the lazy dog.",
+            "     2\tone",
+            "     3\ttwo",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn header() -> Result<()> {
+    run(
+        &["--header", FOX, SPIDERS],
+        "tests/expected/fox-spiders.header.out",
+    )
+}
+
+#[test]
+fn header_format() -> Result<()> {
+    run(
+        &["--header===> {name} ({size} bytes) <==", FOX, SPIDERS],
+        "tests/expected/fox-spiders.header-size.out",
+    )
+}
+
+#[test]
+fn header_stdin_size() -> Result<()> {
+    run_stdin(
+        FOX,
+        &["--header={index}: {name} {size}", "-"],
+        "tests/expected/fox.header-stdin.out",
+    )
+}
+
+#[test]
+fn separator() -> Result<()> {
+    run(
+        &["--separator", "----", FOX, SPIDERS],
+        "tests/expected/fox-spiders.separator.out",
+    )
+}
+
+#[test]
+fn header_separator() -> Result<()> {
+    run(
+        &["--header={index}: {name}", "--separator=----", FOX, SPIDERS],
+        "tests/expected/fox-spiders.header-separator.out",
+    )
+}
+
+#[test]
+fn recursive_header() -> Result<()> {
+    run(&["-r", "--header", TREE], "tests/expected/tree.r.header.out")
+}
+
+#[test]
+fn output_file() -> Result<()> {
+    let path = temp_path();
+    fs::write(&path, "old\n")?;
+    let output = path.to_str().unwrap();
+    let result = (|| -> Result<()> {
+        Command::cargo_bin(PRG)?
+            .args(["-o", output, FOX, SPIDERS])
+            .assert()
+            .success()
+            .stdout("");
+        let expected = fs::read_to_string(FOX)? + &fs::read_to_string(SPIDERS)?;
+        assert_eq!(fs::read_to_string(&path)?, expected);
+
+        Command::cargo_bin(PRG)?
+            .args(["--tee", "-o", output, FOX])
+            .assert()
+            .success()
+            .stdout(fs::read_to_string(FOX)?);
+        assert_eq!(fs::read_to_string(&path)?, fs::read_to_string(FOX)?);
+        Ok(())
+    })();
+    fs::remove_file(&path)?;
+    result
+}
+
+#[test]
+fn output_file_through_links() -> Result<()> {
+    let path = temp_path();
+    let symlink = temp_path();
+    let hard_link = temp_path();
+    fs::write(&path, "old\n")?;
+    std::os::unix::fs::symlink(&path, &symlink)?;
+    fs::hard_link(&path, &hard_link)?;
+    let result = (|| -> Result<()> {
+        Command::cargo_bin(PRG)?
+            .args(["-o", symlink.to_str().unwrap(), FOX])
+            .assert()
+            .success();
+        assert!(fs::symlink_metadata(&symlink)?.is_symlink());
+        assert_eq!(fs::read_to_string(&path)?, fs::read_to_string(FOX)?);
+        assert_eq!(fs::read_to_string(&hard_link)?, fs::read_to_string(FOX)?);
+
+        Command::cargo_bin(PRG)?
+            .args(["-o", hard_link.to_str().unwrap(), SPIDERS])
+            .assert()
+            .success();
+        assert_eq!(fs::read_to_string(&path)?, fs::read_to_string(SPIDERS)?);
+        Ok(())
+    })();
+    for link in [&path, &symlink, &hard_link] {
+        fs::remove_file(link)?;
+    }
+    result
+}
+
+#[test]
+fn output_file_is_input() -> Result<()> {
+    let path = temp_path();
+    fs::write(&path, "keep\n")?;
+    let output = path.to_str().unwrap();
+    let result = Command::cargo_bin(PRG)?
+        .args(["-o", output, FOX, output])
+        .output();
+    let contents = fs::read_to_string(&path)?;
+    fs::remove_file(&path)?;
+    let result = result?;
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(result.stderr)?,
+        format!("catr: {output}: input file is output file\n")
+    );
+    assert_eq!(contents, "keep\n");
+    Ok(())
+}
+
+#[test]
+fn stdout_is_input() -> Result<()> {
+    let path = temp_path();
+    fs::write(&path, "")?;
+    let output = path.to_str().unwrap();
+    let result = std::process::Command::new(cargo_bin(PRG))
+        .args([output, FOX])
+        .stdout(fs::OpenOptions::new().append(true).open(&path)?)
+        .output();
+    let contents = fs::read_to_string(&path)?;
+    fs::remove_file(&path)?;
+    let result = result?;
+    assert_eq!(result.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(result.stderr)?,
+        format!("catr: {output}: input file is output file\n")
+    );
+    assert_eq!(contents, fs::read_to_string(FOX)?);
+    Ok(())
+}
+
+#[test]
+fn recursive_include() -> Result<()> {
+    run(
+        &["--recursive", "--include", "*.conf", TREE],
+        "tests/expected/tree.r.include.out",
+    )
+}
+
+#[test]
+fn recursive_gitignore_exclude() -> Result<()> {
+    run(
+        &["-rn", "--gitignore", "--exclude", ".*", TREE],
+        "tests/expected/tree.r.gitignore.n.out",
+    )
+}
+
+#[test]
+fn dies_include_without_recursive() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--include", "*.conf", TREE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "the following required arguments were not provided",
+        ));
+    Ok(())
+}
@@ -1,14 +1,69 @@
 use anyhow::Result;
-use clap::{Arg, Command };
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read};
+use clap::{Arg, ArgAction, Command};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::collections::VecDeque;
 
 #[derive(Debug)]
 struct Args {
     files: Vec<String>,
-    lines: i64,
-    bytes: Option<u64>,
+    lines: Count,
+    bytes: Option<Count>,
+    quiet: bool,
+    verbose: bool,
+    zero_terminated: bool,
+}
+
+/// A count given to -n or -c. `AllBut` is GNU's negative form, which
+/// keeps -0 (everything) apart from 0 (nothing).
+#[derive(Clone, Copy, Debug)]
+enum Count {
+    First(u64),
+    AllBut(u64),
+}
+
+/// Parses a count with an optional GNU multiplier suffix: b (512), K or
+/// KiB (1024), KB or kB (1000), and likewise M, G, T, P, E, Z, Y, R and Q.
+/// Counts too large for a u64 are clamped.
+fn parse_count(value: &str) -> Result<Count, String> {
+    let (negative, magnitude) = match value.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let digits_end = magnitude
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(magnitude.len());
+    let (number, suffix) = magnitude.split_at(digits_end);
+    let multiplier = match suffix {
+        "" => 1,
+        "b" => 512,
+        "kB" => 1000,
+        "k" => 1024,
+        _ => {
+            let mut chars = suffix.chars();
+            let power = chars.next().and_then(|c| "KMGTPEZYRQ".find(c));
+            let base: u128 = match chars.as_str() {
+                "" | "iB" => 1024,
+                "B" => 1000,
+                _ => 0,
+            };
+            match power {
+                Some(power) if base > 0 => base.pow(power as u32 + 1),
+                // Reports the error for the value as a plain number
+                _ => {
+                    return Err(value.parse::<i64>().unwrap_err().to_string())
+                }
+            }
+        }
+    };
+    let count = number.parse::<u64>().map_err(|e| e.to_string())?;
+    let count = (count as u128).saturating_mul(multiplier);
+    let count = u64::try_from(count).unwrap_or(u64::MAX);
+    Ok(if negative {
+        Count::AllBut(count)
+    } else {
+        Count::First(count)
+    })
 }
 
 fn get_args() -> Args {
@@ -29,9 +84,9 @@ fn get_args() -> Args {
                 .long("lines")
                 .help("Number of lines")
                 .num_args(1)
-                .value_parser(clap::value_parser!(i64))
+                .value_parser(parse_count)
                 .default_value("10")
-                .allow_negative_numbers(true),
+                .allow_hyphen_values(true),
         )
         .arg(
             Arg::new("bytes")
@@ -39,15 +94,43 @@ fn get_args() -> Args {
                 .long("bytes")
                 .value_name("BYTES")
                 .conflicts_with("lines")
-                .value_parser(clap::value_parser!(u64).range(1..))
+                .value_parser(parse_count)
+                .allow_hyphen_values(true)
                 .help("Number of bytes"),
         )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .visible_alias("silent")
+                .help("Never print headers giving file names")
+                .action(ArgAction::SetTrue)
+                .overrides_with("verbose"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Always print headers giving file names")
+                .action(ArgAction::SetTrue)
+                .overrides_with("quiet"),
+        )
+        .arg(
+            Arg::new("zero_terminated")
+                .short('z')
+                .long("zero-terminated")
+                .help("Line delimiter is NUL, not newline")
+                .action(ArgAction::SetTrue),
+        )
         .get_matches();
 
     Args {
         files: arguments.get_many("files").unwrap().cloned().collect(),
         lines: arguments.get_one("lines").cloned().unwrap(),
         bytes: arguments.get_one("bytes").cloned(),
+        quiet: arguments.get_flag("quiet"),
+        verbose: arguments.get_flag("verbose"),
+        zero_terminated: arguments.get_flag("zero_terminated"),
     }
 }
 
@@ -58,52 +141,113 @@ fn open(filename: &str) -> Result<Box<dyn BufRead>> {
     }
 }
 
+/// Prints all but the last `bytes_to_skip` bytes. A regular file's length
+/// is known up front, anything else is held back in a ring buffer.
+fn print_all_but_last_bytes(
+    mut file: Box<dyn BufRead>,
+    file_name: &str,
+    bytes_to_skip: u64,
+) -> Result<()> {
+    let mut stdout = io::stdout();
+    if file_name != "-" {
+        let metadata = fs::metadata(file_name)?;
+        // Files in /proc and the like report a length of 0
+        if metadata.is_file() && metadata.len() > 0 {
+            let bytes_to_print = metadata.len().saturating_sub(bytes_to_skip);
+            io::copy(&mut file.take(bytes_to_print), &mut stdout)?;
+            return Ok(());
+        }
+    }
+
+    let bytes_to_skip = usize::try_from(bytes_to_skip).unwrap_or(usize::MAX);
+    let mut buffer = VecDeque::new();
+    loop {
+        let chunk = file.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        buffer.extend(chunk);
+        let chunk_len = chunk.len();
+        file.consume(chunk_len);
+        if buffer.len() > bytes_to_skip {
+            let excess = buffer.len() - bytes_to_skip;
+            let bytes_to_print: Vec<u8> = buffer.drain(..excess).collect();
+            stdout.write_all(&bytes_to_print)?;
+        }
+    }
+    Ok(())
+}
+
 fn run(args: Args) -> Result<()> {
     let num_of_files = args.files.len();
+    let print_headers = args.verbose || (num_of_files > 1 && !args.quiet);
+    let terminator = if args.zero_terminated { b'\0' } else { b'\n' };
 
     for (file_num, file_name) in args.files.iter().enumerate() {
         match open(file_name) {
             Err(err) => eprintln!("{file_name}: {err}"),
             Ok(mut file) => {
-                if num_of_files > 1 {
+                if print_headers {
                     println!(
-                        "{}==> {file_name} <==",
+                        "{}==> {} <==",
                         if file_num > 0 { "\n" } else { "" },
+                        if file_name == "-" {
+                            "standard input"
+                        } else {
+                            file_name
+                        },
                     );
                 }
 
                 if let Some(num_bytes) = args.bytes {
-                    let mut buffer = vec![0; num_bytes as usize];
-                    let bytes_read = file.read(&mut buffer)?;
-                    print!(
-                        "{}",
-                        String::from_utf8_lossy(&buffer[..bytes_read])
-                    )
-
+                    match num_bytes {
+                        Count::First(num_bytes) => {
+                            // take() keeps reading until num_bytes or EOF,
+                            // as a single read() may return less from a pipe
+                            let mut head = file.take(num_bytes);
+                            io::copy(&mut head, &mut io::stdout())?;
+                        }
+                        Count::AllBut(bytes_to_skip) => {
+                            print_all_but_last_bytes(
+                                file,
+                                file_name,
+                                bytes_to_skip,
+                            )?;
+                        }
+                    }
                 } else {
-                    if args.lines >= 0 {
-                        let mut line = String::new();
-                        for _ in 0..args.lines {
-                            let bytes = file.read_line(&mut line)?;
-                            if bytes == 0 {
-                                break;
+                    // Lines are raw bytes, so -z records may hold any name
+                    let mut stdout = io::stdout();
+                    match args.lines {
+                        Count::First(num_lines) => {
+                            let mut line = Vec::new();
+                            for _ in 0..num_lines {
+                                let bytes =
+                                    file.read_until(terminator, &mut line)?;
+                                if bytes == 0 {
+                                    break;
+                                }
+                                stdout.write_all(&line)?;
+                                line.clear();
                             }
-                            print!("{line}");
-                            line.clear();
                         }
-                    } else {
-                        let lines_to_skip = (-args.lines) as usize;
-                        let mut buffer = VecDeque::new();
-                        let mut line = String::new();
-                        
-                        while file.read_line(&mut line)? > 0 {
-                            buffer.push_back(line.clone());
-                            if buffer.len() > lines_to_skip {
-                                if let Some(line_to_print) = buffer.pop_front() {
-                                    print!("{}", line_to_print);
+                        Count::AllBut(lines_to_skip) => {
+                            let lines_to_skip: usize =
+                                lines_to_skip.try_into().unwrap_or(usize::MAX);
+                            let mut buffer = VecDeque::new();
+                            let mut line = Vec::new();
+
+                            while file.read_until(terminator, &mut line)? > 0 {
+                                buffer.push_back(line.clone());
+                                if buffer.len() > lines_to_skip {
+                                    if let Some(line_to_print) =
+                                        buffer.pop_front()
+                                    {
+                                        stdout.write_all(&line_to_print)?;
+                                    }
                                 }
+                                line.clear();
                             }
-                            line.clear();
                         }
                     }
                 }
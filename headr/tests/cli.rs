@@ -12,6 +12,7 @@ const ONE: &str = "./tests/inputs/one.txt";
 const TWO: &str = "./tests/inputs/two.txt";
 const THREE: &str = "./tests/inputs/three.txt";
 const TWELVE: &str = "./tests/inputs/twelve.txt";
+const ZERO: &str = "./tests/inputs/zero.txt";
 
 // --------------------------------------------------
 fn random_string() -> String {
@@ -484,3 +485,286 @@ fn multiple_files_c4() -> Result<()> {
         "tests/expected/all.c4.out",
     )
 }
+
+// --------------------------------------------------
+fn run_bytes(args: &[&str], expected_file: &str) -> Result<()> {
+    let expected = fs::read(expected_file)?;
+    let output = Command::cargo_bin(PRG)?.args(args).output().expect("fail");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+
+    Ok(())
+}
+
+// --------------------------------------------------
+// Feeds 200,000 patterned bytes to stdin and expects the first
+// `expected_len` of them back
+fn run_large_stdin(args: &[&str], expected_len: usize) -> Result<()> {
+    let input: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    let output = Command::cargo_bin(PRG)?
+        .write_stdin(input.clone())
+        .args(args)
+        .output()
+        .expect("fail");
+    assert!(output.status.success());
+    // Compared without a diff, which is too slow for this much data
+    assert_eq!(output.stdout.len(), expected_len, "{args:?}");
+    assert!(output.stdout == input[..expected_len]);
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn one_c1_raw_bytes() -> Result<()> {
+    run_bytes(&[ONE, "-c", "1"], "tests/expected/one.txt.c1.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn c_reads_past_short_reads() -> Result<()> {
+    run_large_stdin(&["-c", "150000"], 150_000)
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_c_minus_2() -> Result<()> {
+    run(&[EMPTY, "-c", "-2"], "tests/expected/empty.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn empty_c_minus_4() -> Result<()> {
+    run(&[EMPTY, "-c", "-4"], "tests/expected/empty.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn one_c_minus_2() -> Result<()> {
+    run(&[ONE, "-c", "-2"], "tests/expected/one.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn one_c_minus_2_stdin() -> Result<()> {
+    run_stdin(&["-c", "-2"], ONE, "tests/expected/one.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn one_c_minus_4() -> Result<()> {
+    run(&[ONE, "-c", "-4"], "tests/expected/one.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn one_c_minus_4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], ONE, "tests/expected/one.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn two_c_minus_2() -> Result<()> {
+    run(&[TWO, "-c", "-2"], "tests/expected/two.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn two_c_minus_2_stdin() -> Result<()> {
+    run_stdin(&["-c", "-2"], TWO, "tests/expected/two.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn two_c_minus_4() -> Result<()> {
+    run(&[TWO, "-c", "-4"], "tests/expected/two.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn two_c_minus_4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], TWO, "tests/expected/two.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn three_c_minus_2() -> Result<()> {
+    run(&[THREE, "-c", "-2"], "tests/expected/three.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn three_c_minus_2_stdin() -> Result<()> {
+    run_stdin(&["-c", "-2"], THREE, "tests/expected/three.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn three_c_minus_4() -> Result<()> {
+    run(&[THREE, "-c", "-4"], "tests/expected/three.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn three_c_minus_4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], THREE, "tests/expected/three.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn twelve_c_minus_2() -> Result<()> {
+    run(&[TWELVE, "-c", "-2"], "tests/expected/twelve.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn twelve_c_minus_2_stdin() -> Result<()> {
+    run_stdin(&["-c", "-2"], TWELVE, "tests/expected/twelve.txt.cm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn twelve_c_minus_4() -> Result<()> {
+    run(&[TWELVE, "-c", "-4"], "tests/expected/twelve.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn twelve_c_minus_4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], TWELVE, "tests/expected/twelve.txt.cm4.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_files_c_minus_4() -> Result<()> {
+    run(
+        &["-c", "-4", EMPTY, ONE, TWO, THREE, TWELVE],
+        "tests/expected/all.cm4.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn c_minus_stream_longer_than_buffer() -> Result<()> {
+    run_large_stdin(&["-c", "-70000"], 130_000)
+}
+
+// --------------------------------------------------
+#[test]
+fn size_suffixes() -> Result<()> {
+    for (count, expected) in [
+        ("1b", 512),
+        ("1K", 1024),
+        ("2KiB", 2048),
+        ("2KB", 2000),
+        ("3kB", 3000),
+        ("-1KiB", 198_976),
+        ("1M", 200_000),
+        ("1Q", 200_000),
+    ] {
+        run_large_stdin(&["-c", count], expected)?;
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn twelve_n_suffix() -> Result<()> {
+    run(&[TWELVE, "-n", "1K"], "tests/expected/twelve.txt.n1K.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_suffix() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "2KX", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid value '2KX' for '--lines <LINES>'",
+        ));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn one_verbose() -> Result<()> {
+    run(&["-v", "-n", "2", ONE], "tests/expected/one.txt.v.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn two_verbose_stdin() -> Result<()> {
+    run_stdin(&["--verbose"], TWO, "tests/expected/two.txt.v.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_files_quiet() -> Result<()> {
+    for flag in ["-q", "--quiet", "--silent"] {
+        run(
+            &[flag, "-n", "2", EMPTY, ONE, TWO, THREE, TWELVE],
+            "tests/expected/all.q.n2.out",
+        )?;
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn last_of_quiet_and_verbose_wins() -> Result<()> {
+    run(&["-q", "-v", "-n", "2", ONE], "tests/expected/one.txt.v.out")?;
+    run(
+        &["-v", "-q", "-n", "2", EMPTY, ONE, TWO, THREE, TWELVE],
+        "tests/expected/all.q.n2.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_n2() -> Result<()> {
+    run_bytes(&[ZERO, "-n", "2"], "tests/expected/zero.txt.n2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_z_n2() -> Result<()> {
+    run_bytes(&[ZERO, "-z", "-n", "2"], "tests/expected/zero.txt.z.n2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_z_n_minus_2() -> Result<()> {
+    run_bytes(
+        &[ZERO, "--zero-terminated", "-n", "-2"],
+        "tests/expected/zero.txt.z.nm2.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_z_n2_stdin() -> Result<()> {
+    run_stdin(&["-z", "-n", "2"], ZERO, "tests/expected/zero.txt.z.n2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn zero_z_n_minus_2_stdin() -> Result<()> {
+    run_stdin(&["-z", "-n", "-2"], ZERO, "tests/expected/zero.txt.z.nm2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn twelve_c_minus_0() -> Result<()> {
+    run(&[TWELVE, "-c", "-0"], "tests/expected/twelve.txt.cm0.out")?;
+    run_stdin(&["-c", "-0"], TWELVE, "tests/expected/twelve.txt.cm0.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn twelve_n_minus_0() -> Result<()> {
+    run(&[TWELVE, "-n", "-0"], "tests/expected/twelve.txt.nm0.out")?;
+    run_stdin(&["-n", "-0"], TWELVE, "tests/expected/twelve.txt.nm0.out")
+}